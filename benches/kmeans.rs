@@ -0,0 +1,54 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use image_base_colors::{
+    img_utils::{
+        base_colors,
+        histogram::{self, Histogram, HistogramOptions},
+        rgb_color::RgbColor,
+    },
+    kmeans::{cluster_params::ClusterParams, histogram_k_means},
+};
+use std::hint::black_box;
+
+/// 262 144 distinct colors, far more than a photo usually has.
+fn large_histogram() -> Histogram {
+    let mut histogram = Histogram::new();
+    (0..256_u32).for_each(|r| {
+        (0..256_u32).step_by(4).for_each(|g| {
+            (0..256_u32).step_by(16).for_each(|b| {
+                histogram.push_color(&RgbColor::new(r, g, b));
+            });
+        });
+    });
+
+    histogram
+}
+
+fn histogram_k_means(c: &mut Criterion) {
+    let mut group = c.benchmark_group("histogram_k_means");
+    group.sample_size(10);
+
+    let histograms = ["images/cat.png", "images/horses.png"]
+        .iter()
+        .map(|path| {
+            let image = base_colors::open_image(path).unwrap();
+            (
+                *path,
+                histogram::from_image(&image, &HistogramOptions::default()),
+            )
+        })
+        .chain([("large_histogram", large_histogram())]);
+
+    histograms.for_each(|(name, histogram)| {
+        let mut params = ClusterParams::new(8, 30);
+        params.seed = Some(1);
+
+        group.bench_function(name, |b| {
+            b.iter(|| histogram_k_means::cluster(black_box(&histogram), &params))
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, histogram_k_means);
+criterion_main!(benches);
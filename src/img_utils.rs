@@ -1,4 +1,10 @@
+pub mod background;
 pub mod base_colors;
+pub mod color_filter;
+pub mod color_metric;
 pub mod color_point;
+pub mod color_space;
 pub mod histogram;
+pub mod region;
 pub mod rgb_color;
+pub mod spatial_weight;
@@ -0,0 +1,200 @@
+use crate::img_utils::{color_point::ColorPoint, histogram::Histogram, rgb_color::RgbColor};
+use image::RgbaImage;
+use std::{collections::VecDeque, str::FromStr};
+
+/// Significant bits per channel used to find the dominant border color.
+const BORDER_BITS: u32 = 4;
+/// Share of the border pixels the dominant color must cover to be taken as a backdrop.
+const MIN_BORDER_SHARE: f32 = 0.5;
+
+/// What happens to the detected background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackgroundMode {
+    /// The background is reported and still clustered.
+    #[default]
+    Detect,
+    /// The background is reported and left out of the histogram.
+    Exclude,
+}
+
+impl FromStr for BackgroundMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "detect" => Ok(Self::Detect),
+            "exclude" => Ok(Self::Exclude),
+            _ => Err(format!("Unknown background mode: '{value}'.")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackgroundOptions {
+    pub mode: BackgroundMode,
+    /// Largest RGB distance from the border color of a background pixel.
+    pub tolerance: f32,
+}
+
+/// Pixels connected to the image border with the dominant border color.
+#[derive(Debug)]
+pub struct Background {
+    /// Mean color of the background pixels.
+    pub color: RgbColor,
+    /// Share (0..=1) of the opaque pixels covered by the background.
+    pub share: f32,
+    width: u32,
+    mask: Vec<bool>,
+}
+
+impl Background {
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        self.mask[(y * self.width + x) as usize]
+    }
+
+    pub fn color_point(&self) -> ColorPoint {
+        ColorPoint::new(self.color.clone(), self.share)
+    }
+}
+
+/// Flood fills from the border pixels close to the dominant border color.
+/// Pixels with alpha below `alpha_threshold` are neither filled nor counted.
+/// Returns `None` when no color covers enough of the border.
+pub fn detect(
+    img: &RgbaImage,
+    options: &BackgroundOptions,
+    alpha_threshold: u8,
+) -> Option<Background> {
+    let (width, height) = img.dimensions();
+    let is_opaque = |x: u32, y: u32| img.get_pixel(x, y)[3] >= alpha_threshold;
+    let rgb = |x: u32, y: u32| {
+        let pixel = img.get_pixel(x, y);
+        [pixel[0], pixel[1], pixel[2]].map(|value| value as f32)
+    };
+
+    let border = img
+        .enumerate_pixels()
+        .filter(|(x, y, _)| *x == 0 || *y == 0 || *x == width - 1 || *y == height - 1)
+        .filter(|(x, y, _)| is_opaque(*x, *y))
+        .map(|(x, y, _)| (x, y))
+        .collect::<Vec<_>>();
+
+    let mut border_histogram = Histogram::with_bits(BORDER_BITS);
+    border.iter().for_each(|&(x, y)| {
+        let [r, g, b] = rgb(x, y).map(|value| value as u32);
+        border_histogram.push_color(&RgbColor::new(r, g, b));
+    });
+    let dominant = border_histogram
+        .to_vec()
+        .into_iter()
+        .max_by(|a, b| a.weight.total_cmp(&b.weight))?
+        .color
+        .to_f32_vec();
+
+    let squared_tolerance = options.tolerance.powi(2);
+    let is_close = |x: u32, y: u32| {
+        rgb(x, y)
+            .iter()
+            .zip(&dominant)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f32>()
+            <= squared_tolerance
+    };
+
+    let border_count = border.len();
+    let seeds = border
+        .into_iter()
+        .filter(|&(x, y)| is_close(x, y))
+        .collect::<Vec<_>>();
+    if (seeds.len() as f32) < border_count as f32 * MIN_BORDER_SHARE {
+        return None;
+    }
+
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+    let mut mask = vec![false; (width * height) as usize];
+    let mut queue = VecDeque::new();
+    seeds.into_iter().for_each(|(x, y)| {
+        mask[index(x, y)] = true;
+        queue.push_back((x, y));
+    });
+
+    let mut sums = [0_f64; 3];
+    let mut filled_count = 0_usize;
+
+    while let Some((x, y)) = queue.pop_front() {
+        sums.iter_mut()
+            .zip(rgb(x, y))
+            .for_each(|(sum, value)| *sum += value as f64);
+        filled_count += 1;
+
+        let neighbors = [
+            (x > 0).then(|| (x - 1, y)),
+            (y > 0).then(|| (x, y - 1)),
+            (x + 1 < width).then_some((x + 1, y)),
+            (y + 1 < height).then_some((x, y + 1)),
+        ];
+
+        neighbors.into_iter().flatten().for_each(|(x, y)| {
+            if !mask[index(x, y)] && is_opaque(x, y) && is_close(x, y) {
+                mask[index(x, y)] = true;
+                queue.push_back((x, y));
+            }
+        });
+    }
+
+    let opaque_count = img
+        .pixels()
+        .filter(|pixel| pixel[3] >= alpha_threshold)
+        .count();
+    let [r, g, b] = sums.map(|sum| (sum / filled_count as f64).round() as u32);
+
+    Some(Background {
+        color: RgbColor::new(r, g, b),
+        share: filled_count as f32 / opaque_count as f32,
+        width,
+        mask,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const OPTIONS: BackgroundOptions = BackgroundOptions {
+        mode: BackgroundMode::Detect,
+        tolerance: 32.0,
+    };
+
+    #[test]
+    fn detect_success() {
+        // light grey backdrop with a slight gradient, a red product touching the bottom edge
+        // and a backdrop colored hole in the product that is not connected to the border.
+        let img = RgbaImage::from_fn(20, 10, |x, y| match (x, y) {
+            (9..=10, 5..=6) => Rgba([240, 240, 240, 255]),
+            (5..=14, 3..=9) => Rgba([200, 20, 20, 255]),
+            _ => Rgba([230 + x as u8 / 2, 230 + x as u8 / 2, 230 + x as u8 / 2, 255]),
+        });
+
+        let background = detect(&img, &OPTIONS, 128).unwrap();
+
+        assert!(background.contains(0, 0));
+        assert!(background.contains(19, 9));
+        assert!(!background.contains(7, 5));
+        assert!(!background.contains(9, 5));
+        assert_eq!(background.share, (200.0 - 70.0) / 200.0);
+        assert!((230..=240).contains(&background.color.r));
+    }
+
+    #[test]
+    fn busy_border_has_no_background() {
+        let img = RgbaImage::from_fn(20, 10, |x, y| match (x + y) % 3 {
+            0 => Rgba([255, 255, 255, 255]),
+            1 => Rgba([0, 0, 0, 255]),
+            _ => Rgba([0, 0, 255, 255]),
+        });
+
+        assert!(detect(&img, &OPTIONS, 128).is_none());
+        assert!(detect(&RgbaImage::new(20, 10), &OPTIONS, 128).is_none());
+    }
+}
@@ -1,40 +1,83 @@
 use super::color_point::ColorPoint;
-use crate::{img_utils::histogram, kmeans::histogram_k_means};
-use image::{ImageError, Rgb, RgbImage};
+use crate::{
+    img_utils::histogram::{self, HistogramOptions},
+    kmeans::{
+        cluster_count::{self, ClusterCountSelection},
+        cluster_params::ClusterParams,
+        histogram_k_means::KMeansDiagnostics,
+        k_medoids,
+    },
+};
+use image::{ImageError, Rgba, RgbaImage};
+use serde::Serialize;
 use std::cmp::min;
 
-pub fn open_image(path: &str) -> Result<RgbImage, ImageError> {
-    let image = image::open(path)?.to_rgb8();
+#[derive(Serialize, Debug)]
+pub struct Palette {
+    pub base_colors: Vec<ColorPoint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster_count: Option<ClusterCountSelection>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<KMeansDiagnostics>,
+    /// Detected backdrop, weighted by its share of the opaque pixels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<ColorPoint>,
+}
+
+pub fn open_image(path: &str) -> Result<RgbaImage, ImageError> {
+    let image = image::open(path)?.to_rgba8();
 
     Ok(image)
 }
 
-pub fn open_image_from_bytes(data: &[u8]) -> Result<RgbImage, ImageError> {
-    let image = image::load_from_memory(data)?.to_rgb8();
+pub fn open_image_from_bytes(data: &[u8]) -> Result<RgbaImage, ImageError> {
+    let image = image::load_from_memory(data)?.to_rgba8();
 
     Ok(image)
 }
 
 pub fn kmeans_calculate(
-    source_img: &RgbImage,
-    number_of_clusters: u32,
-    max_try_count: u32,
-) -> Vec<ColorPoint> {
-    let histogram = histogram::from_image(source_img);
-    let mut centers = histogram_k_means::cluster(&histogram, number_of_clusters, max_try_count);
+    source_img: &RgbaImage,
+    histogram_options: &HistogramOptions,
+    params: &ClusterParams,
+) -> Palette {
+    let (histogram, background) =
+        histogram::from_image_with_background(source_img, histogram_options);
+
+    let (quantization, cluster_count) = match &params.auto_cluster_count {
+        Some(auto) => {
+            let (quantization, selection) = cluster_count::select(&histogram, params, auto);
+            (quantization, Some(selection))
+        }
+        None => (
+            params.algorithm.quantizer().quantize(&histogram, params),
+            None,
+        ),
+    };
+
+    let mut centers = match params.snap_to_existing {
+        true => k_medoids::snap_to_existing(quantization.centers, &histogram, params),
+        false => quantization.centers,
+    };
     centers.sort_by(|a, b| b.weight.total_cmp(&a.weight));
 
-    centers
+    Palette {
+        base_colors: centers,
+        cluster_count,
+        diagnostics: quantization.diagnostics,
+        background: background.map(|background| background.color_point()),
+    }
 }
 
-pub fn draw(source_img: &RgbImage, base_colors: &[ColorPoint]) -> RgbImage {
+/// Transparent pixels of the source stay transparent, base colors are drawn opaque.
+pub fn draw(source_img: &RgbaImage, base_colors: &[ColorPoint]) -> RgbaImage {
     let (width, height) = source_img.dimensions();
 
     // expand new image width.
     // TODO move to global settings.
     let expand_ratio = 1.2_f32;
     let out_img_width = (width as f32 * expand_ratio) as u32;
-    let mut out_img = RgbImage::new(out_img_width, height);
+    let mut out_img = RgbaImage::new(out_img_width, height);
 
     // write source image to new one.
     source_img.enumerate_pixels().for_each(|(x, y, pixel)| {
@@ -56,7 +99,7 @@ pub fn draw(source_img: &RgbImage, base_colors: &[ColorPoint]) -> RgbImage {
 }
 
 fn draw_base_colors_area(
-    img: &mut RgbImage,
+    img: &mut RgbaImage,
     centers: &[ColorPoint],
     left: u32,
     right: u32,
@@ -70,12 +113,13 @@ fn draw_base_colors_area(
 
         (left..right).for_each(|x| {
             (y_top..y_bottom).for_each(|y| {
-                let rgb = Rgb([
+                let rgba = Rgba([
                     point.color.r as u8,
                     point.color.g as u8,
                     point.color.b as u8,
+                    u8::MAX,
                 ]);
-                img.put_pixel(x, y, rgb);
+                img.put_pixel(x, y, rgba);
             });
         });
 
@@ -0,0 +1,80 @@
+use crate::img_utils::{color_space, rgb_color::RgbColor};
+
+/// Colors left out of the histogram.
+/// Lightness is the CIELAB L (0..=100), chroma the distance from the CIELAB gray axis.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColorFilter {
+    pub min_lightness: Option<f32>,
+    pub max_lightness: Option<f32>,
+    pub min_chroma: Option<f32>,
+    pub ignored_colors: Vec<RgbColor>,
+    /// Largest RGB distance from an ignored color of a left out pixel.
+    pub ignore_tolerance: f32,
+}
+
+impl ColorFilter {
+    /// Whether every color is kept.
+    pub fn is_empty(&self) -> bool {
+        self.min_lightness.is_none()
+            && self.max_lightness.is_none()
+            && self.min_chroma.is_none()
+            && self.ignored_colors.is_empty()
+    }
+
+    /// Whether the color is kept in the histogram.
+    pub fn accepts(&self, color: &RgbColor) -> bool {
+        if self.min_lightness.is_some() || self.max_lightness.is_some() || self.min_chroma.is_some()
+        {
+            let [lightness, a, b] = color_space::lab(color);
+            let chroma = a.hypot(b);
+
+            if self.min_lightness.is_some_and(|min| lightness < min)
+                || self.max_lightness.is_some_and(|max| lightness > max)
+                || self.min_chroma.is_some_and(|min| chroma < min)
+            {
+                return false;
+            }
+        }
+
+        let squared_tolerance = self.ignore_tolerance.powi(2);
+        let channels = [color.r, color.g, color.b];
+
+        !self.ignored_colors.iter().any(|ignored| {
+            channels
+                .iter()
+                .zip([ignored.r, ignored.g, ignored.b])
+                .map(|(a, b)| (*a as f32 - b as f32).powi(2))
+                .sum::<f32>()
+                <= squared_tolerance
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_success() {
+        let filter = ColorFilter {
+            min_lightness: Some(10.0),
+            max_lightness: Some(95.0),
+            min_chroma: Some(20.0),
+            ignored_colors: vec![RgbColor::new(0, 128, 0)],
+            ignore_tolerance: 10.0,
+        };
+
+        assert!(filter.accepts(&RgbColor::new(230, 40, 40)));
+        // paper white, shadow and gray.
+        assert!(!filter.accepts(&RgbColor::new(252, 250, 245)));
+        assert!(!filter.accepts(&RgbColor::new(15, 12, 10)));
+        assert!(!filter.accepts(&RgbColor::new(128, 128, 128)));
+        // ignored color and its neighborhood.
+        assert!(!filter.accepts(&RgbColor::new(5, 133, 0)));
+        assert!(filter.accepts(&RgbColor::new(0, 150, 0)));
+
+        assert!(ColorFilter::default().accepts(&RgbColor::new(128, 128, 128)));
+        assert!(ColorFilter::default().is_empty());
+        assert!(!filter.is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+use crate::img_utils::color_space::ColorSpace;
+use std::str::FromStr;
+
+/// Distance between two colors given by their coordinates.
+pub trait ColorMetric: Sync {
+    fn distance(&self, point_a: &[f32], point_b: &[f32]) -> f32;
+
+    /// Square of the distance, the error k-means minimizes.
+    fn squared_distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        self.distance(point_a, point_b).powi(2)
+    }
+
+    /// Color space the metric is defined in, `None` if it works in any color space.
+    fn color_space(&self) -> Option<ColorSpace> {
+        None
+    }
+}
+
+pub struct Euclidean;
+pub struct SquaredEuclidean;
+pub struct Manhattan;
+/// ΔE*ab (1976), Euclidean distance in CIELAB.
+pub struct Cie76;
+/// ΔE*94 with graphic arts weights.
+pub struct Cie94;
+/// ΔE*00.
+pub struct Ciede2000;
+
+impl ColorMetric for Euclidean {
+    fn distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        SquaredEuclidean.distance(point_a, point_b).sqrt()
+    }
+
+    fn squared_distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        SquaredEuclidean.distance(point_a, point_b)
+    }
+}
+
+impl ColorMetric for SquaredEuclidean {
+    fn distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        let mut sum_squared_diffs = 0_f32;
+
+        point_a.iter().enumerate().for_each(|(i, _)| {
+            sum_squared_diffs += (point_a[i] - point_b[i]).powf(2_f32);
+        });
+
+        sum_squared_diffs
+    }
+
+    /// The distance is already squared, it is the squared error of the Euclidean distance.
+    fn squared_distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        self.distance(point_a, point_b)
+    }
+}
+
+impl ColorMetric for Manhattan {
+    fn distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        point_a
+            .iter()
+            .zip(point_b)
+            .map(|(a, b)| (a - b).abs())
+            .sum()
+    }
+}
+
+impl ColorMetric for Cie76 {
+    fn distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        Euclidean.distance(point_a, point_b)
+    }
+
+    fn squared_distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        Euclidean.squared_distance(point_a, point_b)
+    }
+
+    fn color_space(&self) -> Option<ColorSpace> {
+        Some(ColorSpace::Lab)
+    }
+}
+
+impl ColorMetric for Cie94 {
+    fn distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        const K1: f64 = 0.045;
+        const K2: f64 = 0.015;
+
+        let [l1, a1, b1] = lab(point_a);
+        let [l2, a2, b2] = lab(point_b);
+
+        let c1 = a1.hypot(b1);
+        let c2 = a2.hypot(b2);
+
+        let delta_l = l1 - l2;
+        let delta_c = c1 - c2;
+        let delta_h_squared = ((a1 - a2).powi(2) + (b1 - b2).powi(2) - delta_c.powi(2)).max(0_f64);
+
+        let s_c = 1_f64 + K1 * c1;
+        let s_h = 1_f64 + K2 * c1;
+
+        (delta_l.powi(2) + (delta_c / s_c).powi(2) + delta_h_squared / s_h.powi(2)).sqrt() as f32
+    }
+
+    fn color_space(&self) -> Option<ColorSpace> {
+        Some(ColorSpace::Lab)
+    }
+}
+
+impl ColorMetric for Ciede2000 {
+    fn distance(&self, point_a: &[f32], point_b: &[f32]) -> f32 {
+        let [l1, a1, b1] = lab(point_a);
+        let [l2, a2, b2] = lab(point_b);
+
+        let c_mean = (a1.hypot(b1) + a2.hypot(b2)) / 2_f64;
+        let c_mean_pow7 = c_mean.powi(7);
+        let g = 0.5 * (1_f64 - (c_mean_pow7 / (c_mean_pow7 + 25_f64.powi(7))).sqrt());
+
+        let a1 = (1_f64 + g) * a1;
+        let a2 = (1_f64 + g) * a2;
+
+        let c1 = a1.hypot(b1);
+        let c2 = a2.hypot(b2);
+        let h1 = hue_angle(a1, b1);
+        let h2 = hue_angle(a2, b2);
+
+        let delta_l = l2 - l1;
+        let delta_c = c2 - c1;
+        let delta_h = match c1 * c2 == 0_f64 {
+            true => 0_f64,
+            false => {
+                let diff = h2 - h1;
+                match diff {
+                    d if d > 180_f64 => d - 360_f64,
+                    d if d < -180_f64 => d + 360_f64,
+                    d => d,
+                }
+            }
+        };
+        let delta_h = 2_f64 * (c1 * c2).sqrt() * (delta_h / 2_f64).to_radians().sin();
+
+        let l_mean = (l1 + l2) / 2_f64;
+        let c_mean = (c1 + c2) / 2_f64;
+        let h_mean = match c1 * c2 == 0_f64 {
+            true => h1 + h2,
+            false => match (h1 - h2).abs() <= 180_f64 {
+                true => (h1 + h2) / 2_f64,
+                false => match h1 + h2 < 360_f64 {
+                    true => (h1 + h2 + 360_f64) / 2_f64,
+                    false => (h1 + h2 - 360_f64) / 2_f64,
+                },
+            },
+        };
+
+        let t = 1_f64 - 0.17 * (h_mean - 30_f64).to_radians().cos()
+            + 0.24 * (2_f64 * h_mean).to_radians().cos()
+            + 0.32 * (3_f64 * h_mean + 6_f64).to_radians().cos()
+            - 0.20 * (4_f64 * h_mean - 63_f64).to_radians().cos();
+
+        let delta_theta = 30_f64 * (-((h_mean - 275_f64) / 25_f64).powi(2)).exp();
+        let c_mean_pow7 = c_mean.powi(7);
+        let r_c = 2_f64 * (c_mean_pow7 / (c_mean_pow7 + 25_f64.powi(7))).sqrt();
+        let s_l = 1_f64
+            + (0.015 * (l_mean - 50_f64).powi(2)) / (20_f64 + (l_mean - 50_f64).powi(2)).sqrt();
+        let s_c = 1_f64 + 0.045 * c_mean;
+        let s_h = 1_f64 + 0.015 * c_mean * t;
+        let r_t = -(2_f64 * delta_theta).to_radians().sin() * r_c;
+
+        let l_term = delta_l / s_l;
+        let c_term = delta_c / s_c;
+        let h_term = delta_h / s_h;
+
+        (l_term.powi(2) + c_term.powi(2) + h_term.powi(2) + r_t * c_term * h_term).sqrt() as f32
+    }
+
+    fn color_space(&self) -> Option<ColorSpace> {
+        Some(ColorSpace::Lab)
+    }
+}
+
+fn lab(point: &[f32]) -> [f64; 3] {
+    [point[0] as f64, point[1] as f64, point[2] as f64]
+}
+
+fn hue_angle(a: f64, b: f64) -> f64 {
+    match a == 0_f64 && b == 0_f64 {
+        true => 0_f64,
+        false => b.atan2(a).to_degrees().rem_euclid(360_f64),
+    }
+}
+
+/// Metric selectable by request parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    #[default]
+    Euclidean,
+    SquaredEuclidean,
+    Manhattan,
+    Cie76,
+    Cie94,
+    Ciede2000,
+}
+
+impl FromStr for DistanceMetric {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "euclidean" => Ok(Self::Euclidean),
+            "squared_euclidean" => Ok(Self::SquaredEuclidean),
+            "manhattan" => Ok(Self::Manhattan),
+            "cie76" => Ok(Self::Cie76),
+            "cie94" => Ok(Self::Cie94),
+            "ciede2000" => Ok(Self::Ciede2000),
+            _ => Err(format!("Unknown distance metric: '{value}'.")),
+        }
+    }
+}
+
+impl DistanceMetric {
+    pub fn metric(self) -> &'static dyn ColorMetric {
+        match self {
+            Self::Euclidean => &Euclidean,
+            Self::SquaredEuclidean => &SquaredEuclidean,
+            Self::Manhattan => &Manhattan,
+            Self::Cie76 => &Cie76,
+            Self::Cie94 => &Cie94,
+            Self::Ciede2000 => &Ciede2000,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn euclidean_distance_success() {
+        let point_a = vec![1.0; 4];
+        let point_b = vec![3.0; 4];
+        let actual = Euclidean.distance(&point_a, &point_b);
+        assert_eq!(actual, 4.0);
+
+        let point_a = vec![-1.0; 4];
+        let point_b = vec![-3.0; 4];
+        let actual = Euclidean.distance(&point_a, &point_b);
+        assert_eq!(actual, 4.0);
+
+        let point_a = vec![-1.0; 4];
+        let point_b = vec![1.0; 4];
+        let actual = Euclidean.distance(&point_a, &point_b);
+        assert_eq!(actual, 4.0);
+    }
+
+    #[test]
+    fn squared_distance_success() {
+        let point_a = [1.0, 2.0, 3.0];
+        let point_b = [3.0, 2.0, 3.0];
+
+        assert_eq!(Euclidean.squared_distance(&point_a, &point_b), 4.0);
+        assert_eq!(SquaredEuclidean.distance(&point_a, &point_b), 4.0);
+        assert_eq!(SquaredEuclidean.squared_distance(&point_a, &point_b), 4.0);
+        assert_eq!(Manhattan.squared_distance(&point_a, &point_b), 4.0);
+    }
+
+    #[test]
+    fn manhattan_distance_success() {
+        let actual = Manhattan.distance(&[1.0, -2.0, 3.0], &[2.0, 2.0, 0.0]);
+        assert_eq!(actual, 8.0);
+    }
+
+    #[test]
+    fn ciede2000_reference_values() {
+        // Sharma, Wu, Dalal test data.
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+            ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            (
+                [60.2574, -34.0099, 36.2677],
+                [60.4626, -34.1751, 39.4387],
+                1.2644,
+            ),
+            ([2.0776, 0.0795, -1.135], [0.9033, -0.0636, -0.5514], 0.9082),
+        ];
+
+        pairs.iter().for_each(|(lab_a, lab_b, expected)| {
+            let actual = Ciede2000.distance(lab_a, lab_b);
+            assert!((actual - expected).abs() < 1e-3, "{actual} != {expected}");
+        });
+    }
+
+    #[test]
+    fn cie94_and_cie76_success() {
+        let actual = Cie76.distance(&[50.0, 0.0, 0.0], &[53.0, 4.0, 0.0]);
+        assert_eq!(actual, 5.0);
+
+        // neutral reference color: chroma and hue weights are 1.
+        let actual = Cie94.distance(&[50.0, 0.0, 0.0], &[53.0, 4.0, 0.0]);
+        assert!((actual - 5.0).abs() < 1e-5);
+    }
+}
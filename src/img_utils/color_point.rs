@@ -5,11 +5,22 @@ use serde::Serialize;
 pub struct ColorPoint {
     pub color: RgbColor,
     pub weight: f32,
+    /// Root of the covariance trace, reported by the Gaussian mixture.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spread: Option<f32>,
+    /// Mixing proportion of the Gaussian mixture component, whatever the `weight` mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mixture_weight: Option<f32>,
 }
 
 impl ColorPoint {
     pub fn new(color: RgbColor, weight: f32) -> Self {
-        Self { color, weight }
+        Self {
+            color,
+            weight,
+            spread: None,
+            mixture_weight: None,
+        }
     }
 
     pub fn color_dim() -> usize {
@@ -0,0 +1,222 @@
+use crate::img_utils::rgb_color::RgbColor;
+use std::str::FromStr;
+
+/// Color space in which colors are compared and averaged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    /// Raw sRGB values, every channel in 0..=255.
+    #[default]
+    Srgb,
+    /// Linear (gamma expanded) RGB, every channel in 0..=255.
+    LinearRgb,
+    /// CIELAB with D65 white point, L in 0..=100.
+    Lab,
+    /// OKLab, L in 0..=1.
+    Oklab,
+}
+
+impl FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "srgb" => Ok(Self::Srgb),
+            "linear_rgb" => Ok(Self::LinearRgb),
+            "lab" | "cielab" => Ok(Self::Lab),
+            "oklab" => Ok(Self::Oklab),
+            _ => Err(format!("Unknown color space: '{value}'.")),
+        }
+    }
+}
+
+// D65 reference white.
+const WHITE_X: f32 = 0.950_47;
+const WHITE_Y: f32 = 1.0;
+const WHITE_Z: f32 = 1.088_83;
+
+impl ColorSpace {
+    /// Coordinates of the sRGB color in this color space.
+    pub fn coordinates(self, color: &RgbColor) -> Vec<f32> {
+        match self {
+            Self::Srgb => color.to_f32_vec(),
+            Self::LinearRgb => linear_rgb(color)
+                .iter()
+                .map(|value| value * 255_f32)
+                .collect(),
+            Self::Lab => linear_rgb_to_lab(&linear_rgb(color)).to_vec(),
+            Self::Oklab => linear_rgb_to_oklab(&linear_rgb(color)).to_vec(),
+        }
+    }
+
+    /// Convert coordinates of this color space back to sRGB.
+    pub fn to_rgb(self, values: &[f32]) -> Result<RgbColor, String> {
+        let expected_len = RgbColor::dim();
+
+        if values.len() != expected_len {
+            return Err(format!(
+                "Unable convert {self:?} coordinates to RgbColor, expected number of elements {expected_len} but got {}.",
+                values.len()
+            ));
+        }
+
+        let point = [values[0], values[1], values[2]];
+
+        let linear = match self {
+            Self::Srgb => return RgbColor::from_f32_vec(values),
+            Self::LinearRgb => point.map(|value| value / 255_f32),
+            Self::Lab => lab_to_linear_rgb(&point),
+            Self::Oklab => oklab_to_linear_rgb(&point),
+        };
+
+        let srgb = linear.map(|value| (linear_to_srgb(value) * 255_f32).round());
+
+        Ok(RgbColor::new(
+            srgb[0] as u32,
+            srgb[1] as u32,
+            srgb[2] as u32,
+        ))
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    match value <= 0.040_45 {
+        true => value / 12.92,
+        false => ((value + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0_f32, 1_f32);
+
+    match value <= 0.003_130_8 {
+        true => value * 12.92,
+        false => 1.055 * value.powf(1_f32 / 2.4) - 0.055,
+    }
+}
+
+/// CIELAB coordinates of the sRGB color, without allocating.
+pub fn lab(color: &RgbColor) -> [f32; 3] {
+    linear_rgb_to_lab(&linear_rgb(color))
+}
+
+fn linear_rgb(color: &RgbColor) -> [f32; 3] {
+    [color.r, color.g, color.b].map(|value| srgb_to_linear(value as f32 / 255_f32))
+}
+
+fn linear_rgb_to_lab(rgb: &[f32; 3]) -> [f32; 3] {
+    let [r, g, b] = *rgb;
+
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / WHITE_X;
+    let y = (0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b) / WHITE_Y;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / WHITE_Z;
+
+    let (fx, fy, fz) = (lab_f(x), lab_f(y), lab_f(z));
+
+    [
+        116_f32 * fy - 16_f32,
+        500_f32 * (fx - fy),
+        200_f32 * (fy - fz),
+    ]
+}
+
+fn lab_to_linear_rgb(lab: &[f32; 3]) -> [f32; 3] {
+    let [l, a, b] = *lab;
+
+    let fy = (l + 16_f32) / 116_f32;
+    let fx = fy + a / 500_f32;
+    let fz = fy - b / 200_f32;
+
+    let x = lab_f_inv(fx) * WHITE_X;
+    let y = lab_f_inv(fy) * WHITE_Y;
+    let z = lab_f_inv(fz) * WHITE_Z;
+
+    [
+        3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+    ]
+}
+
+fn lab_f(value: f32) -> f32 {
+    const DELTA: f32 = 6_f32 / 29_f32;
+
+    match value > DELTA.powi(3) {
+        true => value.cbrt(),
+        false => value / (3_f32 * DELTA.powi(2)) + 4_f32 / 29_f32,
+    }
+}
+
+fn lab_f_inv(value: f32) -> f32 {
+    const DELTA: f32 = 6_f32 / 29_f32;
+
+    match value > DELTA {
+        true => value.powi(3),
+        false => 3_f32 * DELTA.powi(2) * (value - 4_f32 / 29_f32),
+    }
+}
+
+fn linear_rgb_to_oklab(rgb: &[f32; 3]) -> [f32; 3] {
+    let [r, g, b] = *rgb;
+
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+fn oklab_to_linear_rgb(lab: &[f32; 3]) -> [f32; 3] {
+    let [l, a, b] = *lab;
+
+    let l_ = (l + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+    let m_ = (l - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+    let s_ = (l - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+
+    [
+        4.076_741_7 * l_ - 3.307_711_6 * m_ + 0.230_969_94 * s_,
+        -1.268_438 * l_ + 2.609_757_4 * m_ - 0.341_319_38 * s_,
+        -0.004_196_086_3 * l_ - 0.703_418_6 * m_ + 1.707_614_7 * s_,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_success() {
+        let spaces = [
+            ColorSpace::Srgb,
+            ColorSpace::LinearRgb,
+            ColorSpace::Lab,
+            ColorSpace::Oklab,
+        ];
+
+        (0..=255_u32).step_by(15).for_each(|r| {
+            (0..=255_u32).step_by(51).for_each(|g| {
+                (0..=255_u32).step_by(85).for_each(|b| {
+                    let color = RgbColor::new(r, g, b);
+
+                    spaces.iter().for_each(|space| {
+                        let actual = space.to_rgb(&space.coordinates(&color)).unwrap();
+                        assert_eq!(actual.to_vec(), color.to_vec(), "{space:?}");
+                    });
+                });
+            });
+        });
+    }
+
+    #[test]
+    fn white_and_black_coordinates() {
+        let white = ColorSpace::Lab.coordinates(&RgbColor::new(255, 255, 255));
+        assert!((white[0] - 100.0).abs() < 0.01);
+        assert!(white[1].abs() < 0.01 && white[2].abs() < 0.01);
+
+        let black = ColorSpace::Oklab.coordinates(&RgbColor::new(0, 0, 0));
+        assert!(black.iter().all(|value| value.abs() < 0.0001));
+    }
+}
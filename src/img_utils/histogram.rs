@@ -1,11 +1,207 @@
-use image::RgbImage;
-use std::collections::HashMap;
+use image::{imageops, imageops::FilterType, RgbaImage};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    hash::{BuildHasherDefault, Hasher},
+    str::FromStr,
+};
 
-use crate::{img_utils::color_point::ColorPoint, img_utils::rgb_color::RgbColor};
+use crate::img_utils::{
+    background::{self, Background, BackgroundMode, BackgroundOptions},
+    color_filter::ColorFilter,
+    color_point::ColorPoint,
+    region::Region,
+    rgb_color::RgbColor,
+    spatial_weight::SpatialWeighting,
+};
+
+/// Significant bits per channel of a full precision histogram.
+pub const MAX_BITS: u32 = 8;
+
+/// Seed of the pixel sampling, the same image always gives the same histogram.
+const SAMPLE_SEED: u64 = 0;
+
+/// Filter used to shrink the image before analysis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeFilter {
+    Nearest,
+    #[default]
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl FromStr for ResizeFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "nearest" => Ok(Self::Nearest),
+            "triangle" => Ok(Self::Triangle),
+            "catmull_rom" => Ok(Self::CatmullRom),
+            "gaussian" => Ok(Self::Gaussian),
+            "lanczos3" => Ok(Self::Lanczos3),
+            _ => Err(format!("Unknown resize filter: '{value}'.")),
+        }
+    }
+}
+
+impl ResizeFilter {
+    fn filter_type(self) -> FilterType {
+        match self {
+            Self::Nearest => FilterType::Nearest,
+            Self::Triangle => FilterType::Triangle,
+            Self::CatmullRom => FilterType::CatmullRom,
+            Self::Gaussian => FilterType::Gaussian,
+            Self::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// What happens to pixels more transparent than `alpha_threshold`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    /// The pixels are left out.
+    #[default]
+    Exclude,
+    /// The pixels count by their opacity.
+    Weight,
+}
+
+impl FromStr for AlphaMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "exclude" => Ok(Self::Exclude),
+            "weight" => Ok(Self::Weight),
+            _ => Err(format!("Unknown alpha mode: '{value}'.")),
+        }
+    }
+}
+
+/// How the histogram is built from the image.
+#[derive(Debug, Clone)]
+pub struct HistogramOptions {
+    /// Significant bits per channel, colors sharing them fall into one bin.
+    pub bits: u32,
+    /// Larger images are shrunk so that their longer side fits this size.
+    pub max_analysis_dimension: Option<u32>,
+    pub resize_filter: ResizeFilter,
+    /// Share of pixels (above 0, at most 1) counted in the histogram, picked at random.
+    pub sample_rate: f32,
+    /// Pixels with lower alpha are handled by `alpha_mode`.
+    pub alpha_threshold: u8,
+    pub alpha_mode: AlphaMode,
+    /// Only pixels of the region are counted.
+    pub region: Region,
+    /// Position based pixel weights, multiplied together.
+    pub spatial_weights: Vec<SpatialWeighting>,
+    /// Backdrop detection, optionally leaving the backdrop out.
+    pub background: Option<BackgroundOptions>,
+    /// Lightness, chroma and ignored colors filters.
+    pub color_filter: ColorFilter,
+    /// Base colors must be colors of the picture (k-medoids, `snap_to_existing`):
+    /// the image is shrunk without blending neighbor pixels, whatever `resize_filter` says,
+    /// and coarse bins keep their member colors.
+    pub existing_colors: bool,
+}
+
+impl Default for HistogramOptions {
+    fn default() -> Self {
+        Self {
+            bits: MAX_BITS,
+            max_analysis_dimension: None,
+            resize_filter: ResizeFilter::default(),
+            sample_rate: 1_f32,
+            alpha_threshold: 128,
+            alpha_mode: AlphaMode::default(),
+            region: Region::default(),
+            spatial_weights: Vec::new(),
+            background: None,
+            color_filter: ColorFilter::default(),
+            existing_colors: false,
+        }
+    }
+}
+
+impl HistogramOptions {
+    /// Histogram weight of a pixel with the given alpha.
+    fn pixel_weight(&self, alpha: u8) -> f32 {
+        match (alpha >= self.alpha_threshold, self.alpha_mode) {
+            (true, _) => 1_f32,
+            (false, AlphaMode::Exclude) => 0_f32,
+            (false, AlphaMode::Weight) => alpha as f32 / 255_f32,
+        }
+    }
+}
+
+type PackedKeyMap<V> = HashMap<u32, V, BuildHasherDefault<PackedKeyHasher>>;
+
+/// Colors of a bin, represented by their mean.
+#[derive(Debug)]
+struct Bin {
+    pixel_count: f32,
+    sums: [f64; 3],
+    /// Pixel count of every color of the bin, kept when a bin can hold more than one color
+    /// and the histogram keeps existing colors.
+    members: PackedKeyMap<f32>,
+}
+
+impl Bin {
+    /// Pixel weighted mean of the bin colors.
+    fn mean_color(&self) -> RgbColor {
+        let pixel_count = self.pixel_count as f64;
+        let [r, g, b] = self.sums.map(|sum| (sum / pixel_count).round() as u32);
+
+        RgbColor::new(r, g, b)
+    }
+
+    /// The most frequent color of the bin, the smallest one of equal counts.
+    fn most_frequent_color(&self) -> RgbColor {
+        self.members
+            .iter()
+            .max_by(|(key_a, count_a), (key_b, count_b)| {
+                count_a.total_cmp(count_b).then(key_b.cmp(key_a))
+            })
+            .map_or_else(
+                || self.mean_color(),
+                |(key, _)| RgbColor::from_packed_key(*key),
+            )
+    }
+}
+
+/// Hasher for packed color keys. Keys are already unique,
+/// a multiplicative hash only spreads them over the hash bits.
+#[derive(Default)]
+struct PackedKeyHasher(u64);
+
+impl Hasher for PackedKeyHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|byte| self.write_u64(*byte as u64));
+    }
+
+    fn write_u32(&mut self, key: u32) {
+        self.write_u64(key as u64);
+    }
+
+    fn write_u64(&mut self, key: u64) {
+        self.0 = (self.0 ^ key).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    }
+}
 
 #[derive(Debug)]
 pub struct Histogram {
-    map: HashMap<u64, ColorPoint>,
+    bits: u32,
+    /// Whether coarse bins keep their member colors for `existing_colors`.
+    keeps_members: bool,
+    map: PackedKeyMap<Bin>,
 }
 
 impl Default for Histogram {
@@ -16,34 +212,469 @@ impl Default for Histogram {
 
 impl Histogram {
     pub fn new() -> Self {
+        Self::with_bits(MAX_BITS)
+    }
+
+    /// Histogram keeping `bits` significant bits per channel (1..=8).
+    pub fn with_bits(bits: u32) -> Self {
         Self {
-            map: HashMap::new(),
+            bits: bits.clamp(1, MAX_BITS),
+            keeps_members: false,
+            map: HashMap::default(),
+        }
+    }
+
+    /// Histogram keeping `bits` significant bits per channel and the colors of every bin,
+    /// so `existing_colors` returns picture colors. Costs a map insert per pixel below 8 bits.
+    pub fn with_existing_colors(bits: u32) -> Self {
+        Self {
+            keeps_members: true,
+            ..Self::with_bits(bits)
         }
     }
 
     pub fn push_color(&mut self, color: &RgbColor) {
-        let entry = self
-            .map
-            .entry(color.hash_key())
-            .or_insert(ColorPoint::new(color.clone(), 0_f32));
+        self.push_weighted_color(color, 1_f32);
+    }
+
+    /// Count the color `weight` times, colors with zero weight are skipped.
+    pub fn push_weighted_color(&mut self, color: &RgbColor, weight: f32) {
+        if weight <= 0_f32 {
+            return;
+        }
+
+        let shift = MAX_BITS - self.bits;
+        let bin_color = RgbColor::new(color.r >> shift, color.g >> shift, color.b >> shift);
+
+        let bin = self.map.entry(bin_color.packed_key()).or_insert(Bin {
+            pixel_count: 0_f32,
+            sums: [0_f64; 3],
+            members: PackedKeyMap::default(),
+        });
+
+        // a full precision bin holds a single color.
+        if self.keeps_members && self.bits < MAX_BITS {
+            *bin.members.entry(color.packed_key()).or_insert(0_f32) += weight;
+        }
+
+        bin.pixel_count += weight;
+        bin.sums
+            .iter_mut()
+            .zip([color.r, color.g, color.b])
+            .for_each(|(sum, value)| *sum += value as f64 * weight as f64);
+    }
 
-        entry.weight += 1_f32;
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
     }
 
+    /// Histogram entries ordered by color, so the output doesn't depend on map iteration order.
+    /// Every bin is represented by the pixel weighted mean of its colors.
     pub fn to_vec(&self) -> Vec<ColorPoint> {
-        let map = &self.map;
+        self.points(Bin::mean_color)
+    }
+
+    /// Histogram entries represented by their most frequent color, so every color is present in the image.
+    /// Used where base colors must be real image colors, the mean of a coarse bin may not be one.
+    /// Coarse bins of a histogram not built `with_existing_colors` fall back to their mean.
+    pub fn existing_colors(&self) -> Vec<ColorPoint> {
+        self.points(Bin::most_frequent_color)
+    }
+
+    fn points(&self, bin_color: impl Fn(&Bin) -> RgbColor) -> Vec<ColorPoint> {
+        let mut vec = self
+            .map
+            .values()
+            .map(|bin| ColorPoint::new(bin_color(bin), bin.pixel_count))
+            .collect::<Vec<_>>();
+        vec.sort_by_key(|entry| (entry.color.r, entry.color.g, entry.color.b));
 
-        map.iter().map(|(_key, entry)| entry.clone()).collect()
+        vec
     }
 }
 
-pub fn from_image(img: &RgbImage) -> Histogram {
-    let mut histogram = Histogram::new();
+pub fn from_image(img: &RgbaImage, options: &HistogramOptions) -> Histogram {
+    from_image_with_background(img, options).0
+}
+
+/// Histogram along with the background detected on the analysis image, when asked for.
+pub fn from_image_with_background(
+    img: &RgbaImage,
+    options: &HistogramOptions,
+) -> (Histogram, Option<Background>) {
+    let mut histogram = match options.existing_colors {
+        true => Histogram::with_existing_colors(options.bits),
+        false => Histogram::with_bits(options.bits),
+    };
+    let (width, height) = img.dimensions();
+    let analysis_img = analysis_image(img, options);
+    let mut rng = StdRng::seed_from_u64(SAMPLE_SEED);
 
-    img.pixels().for_each(|pixel| {
-        let color = RgbColor::new(pixel[0] as u32, pixel[1] as u32, pixel[2] as u32);
-        histogram.push_color(&color);
+    let (analysis_width, analysis_height) = analysis_img.dimensions();
+    let spatial_weights = options
+        .spatial_weights
+        .iter()
+        .map(|weighting| weighting.spatial_weight(&analysis_img))
+        .collect::<Vec<_>>();
+    let background = options.background.as_ref().and_then(|background_options| {
+        background::detect(&analysis_img, background_options, options.alpha_threshold)
     });
+    // the filter only depends on the color, every distinct color is checked once.
+    let mut filtered_colors = PackedKeyMap::<bool>::default();
+    let mut accepts = |color: &RgbColor| {
+        options.color_filter.is_empty()
+            || *filtered_colors
+                .entry(color.packed_key())
+                .or_insert_with(|| options.color_filter.accepts(color))
+    };
+    let excluded_background = background.as_ref().filter(|_| {
+        options
+            .background
+            .is_some_and(|b| b.mode == BackgroundMode::Exclude)
+    });
+
+    // region coordinates are given in source image pixels.
+    let scale_x = width as f32 / analysis_width as f32;
+    let scale_y = height as f32 / analysis_height as f32;
+
+    analysis_img
+        .enumerate_pixels()
+        .filter(|_| options.sample_rate >= 1_f32 || rng.gen::<f32>() < options.sample_rate)
+        .filter(|(x, y, _)| excluded_background.map_or(true, |b| !b.contains(*x, *y)))
+        .for_each(|(x, y, pixel)| {
+            let region_weight = options.region.weight(
+                (x as f32 + 0.5) * scale_x,
+                (y as f32 + 0.5) * scale_y,
+                width,
+                height,
+            );
+            let spatial_weight = spatial_weights
+                .iter()
+                .map(|spatial_weight| {
+                    spatial_weight.weight(
+                        (x as f32 + 0.5) / analysis_width as f32,
+                        (y as f32 + 0.5) / analysis_height as f32,
+                    )
+                })
+                .product::<f32>();
+
+            let color = RgbColor::new(pixel[0] as u32, pixel[1] as u32, pixel[2] as u32);
+            if !accepts(&color) {
+                return;
+            }
+
+            histogram.push_weighted_color(
+                &color,
+                options.pixel_weight(pixel[3]) * region_weight * spatial_weight,
+            );
+        });
+
+    (histogram, background)
+}
+
+/// The image shrunk to `max_analysis_dimension`, the image itself if it already fits.
+fn analysis_image<'a>(img: &'a RgbaImage, options: &HistogramOptions) -> Cow<'a, RgbaImage> {
+    let (width, height) = img.dimensions();
+
+    let max_dimension = match options.max_analysis_dimension {
+        Some(value) if value > 0 && width.max(height) > value => value,
+        _ => return Cow::Borrowed(img),
+    };
+
+    let scale = max_dimension as f64 / width.max(height) as f64;
+    let new_width = ((width as f64 * scale).round() as u32).max(1);
+    let new_height = ((height as f64 * scale).round() as u32).max(1);
+    // blending filters make colors the picture doesn't have.
+    let filter = match options.existing_colors {
+        true => FilterType::Nearest,
+        false => options.resize_filter.filter_type(),
+    };
 
-    histogram
+    Cow::Owned(imageops::resize(img, new_width, new_height, filter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bins_are_pixel_weighted_means() {
+        let mut histogram = Histogram::with_bits(4);
+        (0..3).for_each(|_| histogram.push_color(&RgbColor::new(16, 0, 255)));
+        histogram.push_color(&RgbColor::new(31, 12, 240));
+        histogram.push_color(&RgbColor::new(32, 0, 255));
+
+        let vec = histogram.to_vec();
+
+        assert_eq!(vec.len(), 2);
+        assert_eq!(vec[0].color.to_vec(), vec![20, 3, 251]);
+        assert_eq!(vec[0].weight, 4.0);
+        assert_eq!(vec[1].color.to_vec(), vec![32, 0, 255]);
+        assert_eq!(vec[1].weight, 1.0);
+    }
+
+    #[test]
+    fn existing_colors_are_most_frequent_members() {
+        let mut histogram = Histogram::with_existing_colors(4);
+        histogram.push_color(&RgbColor::new(0, 0, 0));
+        (0..2).for_each(|_| histogram.push_color(&RgbColor::new(15, 15, 15)));
+        histogram.push_weighted_color(&RgbColor::new(200, 0, 0), 0.5);
+        histogram.push_weighted_color(&RgbColor::new(201, 0, 0), 0.5);
+
+        let means = histogram.to_vec();
+        assert_eq!(means[0].color.to_vec(), vec![10, 10, 10]);
+
+        let existing = histogram.existing_colors();
+        assert_eq!(existing.len(), 2);
+        assert_eq!(existing[0].color.to_vec(), vec![15, 15, 15]);
+        assert_eq!(existing[0].weight, 3.0);
+        // equal counts, the smaller color wins.
+        assert_eq!(existing[1].color.to_vec(), vec![200, 0, 0]);
+        assert_eq!(existing[1].weight, 1.0);
+    }
+
+    #[test]
+    fn coarse_bins_keep_members_only_when_asked() {
+        let mut histogram = Histogram::with_bits(4);
+        histogram.push_color(&RgbColor::new(0, 0, 0));
+        (0..2).for_each(|_| histogram.push_color(&RgbColor::new(15, 15, 15)));
+
+        assert!(histogram.map.values().all(|bin| bin.members.is_empty()));
+        assert_eq!(
+            histogram.existing_colors()[0].color.to_vec(),
+            vec![10, 10, 10]
+        );
+    }
+
+    #[test]
+    fn every_color_is_counted_apart() {
+        let mut histogram = Histogram::new();
+        (0..=255_u32).step_by(3).for_each(|r| {
+            (0..=255_u32).step_by(5).for_each(|g| {
+                (0..=255_u32).step_by(17).for_each(|b| {
+                    histogram.push_color(&RgbColor::new(r, g, b));
+                    if (r + g + b) % 2 == 0 {
+                        histogram.push_color(&RgbColor::new(r, g, b));
+                    }
+                });
+            });
+        });
+
+        let vec = histogram.to_vec();
+
+        assert_eq!(vec.len(), 86 * 52 * 16);
+        vec.iter().for_each(|point| {
+            let expected = 1 + (point.color.r + point.color.g + point.color.b + 1) % 2;
+            assert_eq!(point.weight, expected as f32);
+        });
+    }
+
+    #[test]
+    fn from_image_with_resize_and_sampling() {
+        let img = RgbaImage::from_fn(400, 200, |x, _| match x < 100 {
+            true => image::Rgba([255, 0, 0, 255]),
+            false => image::Rgba([0, 0, 255, 255]),
+        });
+        let pixel_count = |options: &HistogramOptions| {
+            from_image(&img, options)
+                .to_vec()
+                .iter()
+                .map(|point| point.weight)
+                .sum::<f32>()
+        };
+
+        let mut options = HistogramOptions {
+            max_analysis_dimension: Some(100),
+            resize_filter: ResizeFilter::Nearest,
+            ..HistogramOptions::default()
+        };
+        assert_eq!(pixel_count(&options), 100.0 * 50.0);
+
+        let vec = from_image(&img, &options).to_vec();
+        assert_eq!(vec.len(), 2);
+        assert_eq!(vec[1].weight, 25.0 * 50.0);
+
+        options.max_analysis_dimension = None;
+        options.sample_rate = 0.1;
+        let sampled = pixel_count(&options);
+        assert!((sampled - 8000.0).abs() < 400.0, "{sampled}");
+        assert_eq!(sampled, pixel_count(&options));
+    }
+
+    #[test]
+    fn existing_colors_resize_without_blending() {
+        // one pixel wide stripes blend into purple.
+        let img = RgbaImage::from_fn(100, 100, |x, _| match x % 2 == 0 {
+            true => image::Rgba([255, 0, 0, 255]),
+            false => image::Rgba([0, 0, 255, 255]),
+        });
+        let colors = |options: &HistogramOptions| {
+            from_image(&img, options)
+                .to_vec()
+                .into_iter()
+                .map(|point| point.color.to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        let mut options = HistogramOptions {
+            max_analysis_dimension: Some(30),
+            resize_filter: ResizeFilter::Triangle,
+            ..HistogramOptions::default()
+        };
+        assert!(colors(&options)
+            .iter()
+            .any(|color| color[0] > 0 && color[2] > 0));
+
+        options.existing_colors = true;
+        colors(&options).iter().for_each(|color| {
+            assert!(*color == vec![255, 0, 0] || *color == vec![0, 0, 255]);
+        });
+    }
+
+    #[test]
+    fn from_image_handles_transparency() {
+        let img = RgbaImage::from_fn(10, 1, |x, _| match x {
+            0..=5 => image::Rgba([0, 0, 0, 0]),
+            6 | 7 => image::Rgba([200, 0, 0, 51]),
+            _ => image::Rgba([0, 200, 0, 255]),
+        });
+
+        let weights = |alpha_mode| {
+            let options = HistogramOptions {
+                alpha_mode,
+                ..HistogramOptions::default()
+            };
+            from_image(&img, &options)
+                .to_vec()
+                .iter()
+                .map(|point| (point.color.to_vec(), point.weight))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(weights(AlphaMode::Exclude), vec![(vec![0, 200, 0], 2.0)]);
+        assert_eq!(
+            weights(AlphaMode::Weight),
+            vec![(vec![0, 200, 0], 2.0), (vec![200, 0, 0], 0.4)]
+        );
+    }
+
+    #[test]
+    fn from_image_excludes_background() {
+        let img = RgbaImage::from_fn(40, 20, |x, y| match (x, y) {
+            (10..=29, 5..=14) => image::Rgba([200, 20, 20, 255]),
+            _ => image::Rgba([245, 245, 245, 255]),
+        });
+
+        let mut options = HistogramOptions {
+            max_analysis_dimension: Some(20),
+            resize_filter: ResizeFilter::Nearest,
+            background: Some(BackgroundOptions {
+                mode: BackgroundMode::Detect,
+                tolerance: 24.0,
+            }),
+            ..HistogramOptions::default()
+        };
+
+        let (histogram, background) = from_image_with_background(&img, &options);
+        assert_eq!(histogram.to_vec().len(), 2);
+        let background = background.unwrap();
+        assert_eq!(background.color.to_vec(), vec![245, 245, 245]);
+        assert_eq!(background.share, 0.75);
+
+        options.background = options.background.map(|background| BackgroundOptions {
+            mode: BackgroundMode::Exclude,
+            ..background
+        });
+        let vec = from_image(&img, &options).to_vec();
+        assert_eq!(vec.len(), 1);
+        assert_eq!(vec[0].color.to_vec(), vec![200, 20, 20]);
+        assert_eq!(vec[0].weight, 10.0 * 5.0);
+    }
+
+    #[test]
+    fn from_image_filters_colors() {
+        let img = RgbaImage::from_fn(4, 1, |x, _| match x {
+            0 => image::Rgba([250, 250, 250, 255]),
+            1 => image::Rgba([10, 10, 10, 255]),
+            2 => image::Rgba([20, 180, 220, 255]),
+            _ => image::Rgba([240, 120, 0, 255]),
+        });
+
+        let options = HistogramOptions {
+            color_filter: ColorFilter {
+                min_lightness: Some(10.0),
+                max_lightness: Some(95.0),
+                ignored_colors: vec![RgbColor::new(245, 125, 0)],
+                ignore_tolerance: 8.0,
+                ..ColorFilter::default()
+            },
+            ..HistogramOptions::default()
+        };
+
+        let vec = from_image(&img, &options).to_vec();
+        assert_eq!(vec.len(), 1);
+        assert_eq!(vec[0].color.to_vec(), vec![20, 180, 220]);
+    }
+
+    #[test]
+    fn from_image_counts_region_in_source_coordinates() {
+        let img = RgbaImage::from_fn(200, 100, |x, _| match x < 100 {
+            true => image::Rgba([255, 0, 0, 255]),
+            false => image::Rgba([0, 0, 255, 255]),
+        });
+
+        let options = HistogramOptions {
+            max_analysis_dimension: Some(20),
+            resize_filter: ResizeFilter::Nearest,
+            region: Region {
+                rectangle: "100,0,100,100".parse().ok(),
+                ..Region::default()
+            },
+            ..HistogramOptions::default()
+        };
+
+        let vec = from_image(&img, &options).to_vec();
+        assert_eq!(vec.len(), 1);
+        assert_eq!(vec[0].color.to_vec(), vec![0, 0, 255]);
+        assert_eq!(vec[0].weight, 10.0 * 10.0);
+    }
+
+    #[test]
+    fn from_image_with_spatial_weights() {
+        // red frame around a blue center.
+        let img = RgbaImage::from_fn(10, 10, |x, y| {
+            match (2..8).contains(&x) && (2..8).contains(&y) {
+                true => image::Rgba([0, 0, 255, 255]),
+                false => image::Rgba([255, 0, 0, 255]),
+            }
+        });
+
+        let mut options = HistogramOptions {
+            spatial_weights: vec![SpatialWeighting::BorderMargin { margin: 0.2 }],
+            ..HistogramOptions::default()
+        };
+        let vec = from_image(&img, &options).to_vec();
+        assert_eq!(vec.len(), 1);
+        assert_eq!(vec[0].weight, 36.0);
+
+        options.spatial_weights = vec![SpatialWeighting::CenterBias { sigma: 0.2 }];
+        let vec = from_image(&img, &options).to_vec();
+        assert_eq!(vec.len(), 2);
+        // 36 blue pixels outweigh 64 red ones.
+        assert!(vec[0].weight > vec[1].weight);
+    }
+
+    #[test]
+    fn full_precision_keeps_colors() {
+        let mut histogram = Histogram::new();
+        histogram.push_color(&RgbColor::new(1, 2, 3));
+        histogram.push_color(&RgbColor::new(1, 2, 4));
+
+        let colors = histogram
+            .to_vec()
+            .iter()
+            .map(|point| point.color.to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(colors, vec![vec![1, 2, 3], vec![1, 2, 4]]);
+    }
 }
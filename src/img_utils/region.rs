@@ -0,0 +1,185 @@
+use image::GrayImage;
+use std::str::FromStr;
+
+/// Crop box in source image pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rectangle {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Parses `x,y,w,h`.
+impl FromStr for Rectangle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let values = parse_list::<u32>(value)?;
+
+        match values[..] {
+            [x, y, width, height] => Ok(Self {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(format!("Rectangle must be 'x,y,w,h', got: '{value}'.")),
+        }
+    }
+}
+
+impl Rectangle {
+    fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x as f32
+            && y >= self.y as f32
+            && x < self.x.saturating_add(self.width) as f32
+            && y < self.y.saturating_add(self.height) as f32
+    }
+}
+
+/// Closed polygon in source image pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub points: Vec<[f32; 2]>,
+}
+
+/// Parses `x1,y1,x2,y2,x3,y3,...`, at least three points.
+impl FromStr for Polygon {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let values = parse_list::<f32>(value)?;
+
+        if values.len() < 6 || values.len() % 2 != 0 {
+            return Err(format!(
+                "Polygon must be at least three 'x,y' pairs, got: '{value}'."
+            ));
+        }
+
+        Ok(Self {
+            points: values.chunks(2).map(|pair| [pair[0], pair[1]]).collect(),
+        })
+    }
+}
+
+impl Polygon {
+    /// Even-odd rule.
+    fn contains(&self, x: f32, y: f32) -> bool {
+        let mut inside = false;
+        let mut previous = self.points[self.points.len() - 1];
+
+        self.points.iter().for_each(|&current| {
+            let [x0, y0] = previous;
+            let [x1, y1] = current;
+
+            if (y0 > y) != (y1 > y) && x < x0 + (y - y0) * (x1 - x0) / (y1 - y0) {
+                inside = !inside;
+            }
+
+            previous = current;
+        });
+
+        inside
+    }
+}
+
+/// Part of the image the palette is taken from.
+/// All given parts must select a pixel, the mask intensity weights it.
+#[derive(Debug, Clone, Default)]
+pub struct Region {
+    pub rectangle: Option<Rectangle>,
+    pub polygon: Option<Polygon>,
+    /// Grayscale mask, stretched over the image when the sizes differ.
+    pub mask: Option<GrayImage>,
+}
+
+impl Region {
+    /// Weight (0..=1) of the pixel with the center at `x, y` of an image of the given size.
+    pub fn weight(&self, x: f32, y: f32, width: u32, height: u32) -> f32 {
+        if self
+            .rectangle
+            .is_some_and(|rectangle| !rectangle.contains(x, y))
+        {
+            return 0_f32;
+        }
+
+        if self
+            .polygon
+            .as_ref()
+            .is_some_and(|polygon| !polygon.contains(x, y))
+        {
+            return 0_f32;
+        }
+
+        match &self.mask {
+            Some(mask) => {
+                let (mask_width, mask_height) = mask.dimensions();
+                let mask_x = ((x * mask_width as f32 / width as f32) as u32)
+                    .min(mask_width.saturating_sub(1));
+                let mask_y = ((y * mask_height as f32 / height as f32) as u32)
+                    .min(mask_height.saturating_sub(1));
+
+                mask.get_pixel(mask_x, mask_y)[0] as f32 / 255_f32
+            }
+            None => 1_f32,
+        }
+    }
+}
+
+fn parse_list<T: FromStr>(value: &str) -> Result<Vec<T>, String> {
+    value
+        .split(',')
+        .map(|item| {
+            item.trim()
+                .parse::<T>()
+                .map_err(|_| format!("Invalid number '{item}' in '{value}'."))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_success() {
+        let rectangle = "10,20,30,40".parse::<Rectangle>().unwrap();
+        assert_eq!(
+            rectangle,
+            Rectangle {
+                x: 10,
+                y: 20,
+                width: 30,
+                height: 40
+            }
+        );
+        assert!("10,20,30".parse::<Rectangle>().is_err());
+
+        let polygon = "0,0, 10,0, 0,10".parse::<Polygon>().unwrap();
+        assert_eq!(polygon.points, vec![[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]]);
+        assert!("0,0,10,0".parse::<Polygon>().is_err());
+    }
+
+    #[test]
+    fn region_weight_success() {
+        let region = Region {
+            rectangle: Some(Rectangle {
+                x: 0,
+                y: 0,
+                width: 8,
+                height: 8,
+            }),
+            polygon: Some("0,0,10,0,0,10".parse().unwrap()),
+            mask: Some(GrayImage::from_fn(5, 5, |x, _| image::Luma([x as u8 * 51]))),
+        };
+
+        // outside of the polygon.
+        assert_eq!(region.weight(7.5, 7.5, 10, 10), 0.0);
+        // outside of the rectangle.
+        assert_eq!(region.weight(8.5, 0.5, 10, 10), 0.0);
+        // mask is stretched, image x = 6.5 is mask x = 3.
+        assert_eq!(region.weight(6.5, 0.5, 10, 10), 0.6);
+        assert_eq!(Region::default().weight(6.5, 0.5, 10, 10), 1.0);
+    }
+}
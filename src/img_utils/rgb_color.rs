@@ -1,10 +1,7 @@
 use serde::Serialize;
-use std::{
-    cmp::Ordering,
-    hash::{DefaultHasher, Hasher},
-};
+use std::{cmp::Ordering, str::FromStr};
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct RgbColor {
     pub r: u32,
     pub g: u32,
@@ -12,8 +9,13 @@ pub struct RgbColor {
 }
 
 impl RgbColor {
+    /// Channels are 8 bit, larger values are clamped to `255`.
     pub fn new(r: u32, g: u32, b: u32) -> Self {
-        Self { r, g, b }
+        Self {
+            r: r.min(0xFF),
+            g: g.min(0xFF),
+            b: b.min(0xFF),
+        }
     }
 
     pub fn from_vec(values: &[u32]) -> Option<Self> {
@@ -44,14 +46,14 @@ impl RgbColor {
         3
     }
 
-    pub fn hash_key(&self) -> u64 {
-        let mut hasher = DefaultHasher::new();
-
-        hasher.write(&self.r.to_ne_bytes());
-        hasher.write(&self.g.to_ne_bytes());
-        hasher.write(&self.b.to_ne_bytes());
+    /// 24-bit `0xRRGGBB` key, unique for every 8 bit color.
+    /// Channels set above `255` directly are clamped like in `new`, they don't wrap around.
+    pub fn packed_key(&self) -> u32 {
+        (self.r.min(0xFF) << 16) | (self.g.min(0xFF) << 8) | self.b.min(0xFF)
+    }
 
-        hasher.finish()
+    pub fn from_packed_key(key: u32) -> Self {
+        Self::new((key >> 16) & 0xFF, (key >> 8) & 0xFF, key & 0xFF)
     }
 
     pub fn to_vec(&self) -> Vec<u32> {
@@ -62,3 +64,62 @@ impl RgbColor {
         vec![self.r as f32, self.g as f32, self.b as f32]
     }
 }
+
+/// Parses hex `rrggbb`, with or without a leading `#`.
+impl FromStr for RgbColor {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let trimmed = value.trim();
+        let hex = trimmed.strip_prefix('#').unwrap_or(trimmed);
+
+        match u32::from_str_radix(hex, 16) {
+            Ok(packed) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => Ok(
+                Self::new((packed >> 16) & 0xFF, (packed >> 8) & 0xFF, packed & 0xFF),
+            ),
+            _ => Err(format!("Color must be hex 'rrggbb', got: '{value}'.")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_key_success() {
+        assert_eq!(RgbColor::new(0x12, 0x34, 0x56).packed_key(), 0x123456);
+        assert_eq!(RgbColor::new(255, 255, 255).packed_key(), 0xFFFFFF);
+        assert_eq!(RgbColor::new(0, 0, 0).packed_key(), 0);
+        assert_eq!(
+            RgbColor::from_packed_key(0x123456),
+            RgbColor::new(0x12, 0x34, 0x56)
+        );
+    }
+
+    #[test]
+    fn channels_above_255_are_clamped() {
+        assert_eq!(RgbColor::new(256, 300, 1000), RgbColor::new(255, 255, 255));
+        assert_ne!(
+            RgbColor::new(256, 0, 0).packed_key(),
+            RgbColor::new(0, 0, 0).packed_key()
+        );
+
+        let color = RgbColor { r: 0, g: 256, b: 0 };
+        assert_eq!(color.packed_key(), 0x00FF00);
+    }
+
+    #[test]
+    fn parse_hex_success() {
+        assert_eq!("#12a4ff".parse(), Ok(RgbColor::new(0x12, 0xA4, 0xFF)));
+        assert_eq!("000000".parse(), Ok(RgbColor::new(0, 0, 0)));
+        assert!("#12a4f".parse::<RgbColor>().is_err());
+        assert!("+12a4f".parse::<RgbColor>().is_err());
+    }
+
+    #[test]
+    fn parse_hex_rejects_repeated_hash() {
+        assert!("##12a4ff".parse::<RgbColor>().is_err());
+        assert!("###12a4ff".parse::<RgbColor>().is_err());
+    }
+}
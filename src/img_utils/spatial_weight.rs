@@ -0,0 +1,179 @@
+use image::{imageops, GrayImage, Luma, RgbaImage};
+
+/// Weight (0..=1) of a pixel by its position.
+/// `x` and `y` are the pixel center relative to the image size (0..=1).
+pub trait SpatialWeight {
+    fn weight(&self, x: f32, y: f32) -> f32;
+}
+
+/// Gaussian falloff from the image center, `sigma` is relative to the image size.
+pub struct CenterBias {
+    sigma: f32,
+}
+
+impl SpatialWeight for CenterBias {
+    fn weight(&self, x: f32, y: f32) -> f32 {
+        let squared_distance = (x - 0.5).powi(2) + (y - 0.5).powi(2);
+
+        (-squared_distance / (2_f32 * self.sigma.powi(2))).exp()
+    }
+}
+
+/// Pixels closer to the image border than `margin` (relative to the image size) are left out.
+pub struct BorderMargin {
+    margin: f32,
+}
+
+impl SpatialWeight for BorderMargin {
+    fn weight(&self, x: f32, y: f32) -> f32 {
+        let inside = |value: f32| value >= self.margin && value <= 1_f32 - self.margin;
+
+        match inside(x) && inside(y) {
+            true => 1_f32,
+            false => 0_f32,
+        }
+    }
+}
+
+/// Blurred gradient magnitude of the luma: edges and contrasted details weigh more than flat areas.
+pub struct Saliency {
+    map: GrayImage,
+}
+
+/// Weight of the least salient pixels, flat parts of the subject still count a little.
+const MIN_SALIENCY: f32 = 0.05;
+/// Blur radius of the gradient map relative to the longer image side.
+const SALIENCY_BLUR: f32 = 0.02;
+/// The map is calculated on a copy of the image shrunk to this size, it is looked up by relative position.
+const SALIENCY_MAP_SIZE: u32 = 256;
+
+impl Saliency {
+    fn new(img: &RgbaImage) -> Self {
+        let mut luma = imageops::grayscale(img);
+        let (width, height) = luma.dimensions();
+
+        if width.max(height) > SALIENCY_MAP_SIZE {
+            let scale = SALIENCY_MAP_SIZE as f32 / width.max(height) as f32;
+            luma = imageops::resize(
+                &luma,
+                ((width as f32 * scale).round() as u32).max(1),
+                ((height as f32 * scale).round() as u32).max(1),
+                imageops::FilterType::Triangle,
+            );
+        }
+
+        let (width, height) = luma.dimensions();
+        let value = |x: i64, y: i64| {
+            let x = x.clamp(0, width as i64 - 1) as u32;
+            let y = y.clamp(0, height as i64 - 1) as u32;
+            luma.get_pixel(x, y)[0] as f32
+        };
+
+        // Sobel operator.
+        let gradients = (0..height as i64)
+            .flat_map(|y| (0..width as i64).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let gx = value(x + 1, y - 1) + 2_f32 * value(x + 1, y) + value(x + 1, y + 1)
+                    - value(x - 1, y - 1)
+                    - 2_f32 * value(x - 1, y)
+                    - value(x - 1, y + 1);
+                let gy = value(x - 1, y + 1) + 2_f32 * value(x, y + 1) + value(x + 1, y + 1)
+                    - value(x - 1, y - 1)
+                    - 2_f32 * value(x, y - 1)
+                    - value(x + 1, y - 1);
+
+                gx.hypot(gy)
+            })
+            .collect::<Vec<_>>();
+
+        let max = gradients.iter().copied().fold(0_f32, f32::max);
+        let gradient_map = GrayImage::from_fn(width, height, |x, y| {
+            let gradient = gradients[(y * width + x) as usize];
+            match max > 0_f32 {
+                true => Luma([(gradient / max * 255_f32).round() as u8]),
+                false => Luma([0]),
+            }
+        });
+
+        let sigma = width.max(height) as f32 * SALIENCY_BLUR;
+        let mut map = imageops::blur(&gradient_map, sigma);
+
+        // stretch the blurred map back to the full range.
+        let max = map.pixels().map(|pixel| pixel[0]).max().unwrap_or(0);
+        if max > 0 {
+            map.pixels_mut().for_each(|pixel| {
+                pixel[0] = (pixel[0] as u32 * 255 / max as u32) as u8;
+            });
+        }
+
+        Self { map }
+    }
+}
+
+impl SpatialWeight for Saliency {
+    fn weight(&self, x: f32, y: f32) -> f32 {
+        let (width, height) = self.map.dimensions();
+        let map_x = ((x * width as f32) as u32).min(width.saturating_sub(1));
+        let map_y = ((y * height as f32) as u32).min(height.saturating_sub(1));
+        let saliency = self.map.get_pixel(map_x, map_y)[0] as f32 / 255_f32;
+
+        MIN_SALIENCY + (1_f32 - MIN_SALIENCY) * saliency
+    }
+}
+
+/// Pixel weighting selectable by request parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpatialWeighting {
+    CenterBias { sigma: f32 },
+    Saliency,
+    BorderMargin { margin: f32 },
+}
+
+impl SpatialWeighting {
+    /// Weighting prepared for the image the histogram is built from.
+    pub fn spatial_weight(self, img: &RgbaImage) -> Box<dyn SpatialWeight> {
+        match self {
+            Self::CenterBias { sigma } => Box::new(CenterBias {
+                sigma: sigma.max(f32::EPSILON),
+            }),
+            Self::Saliency => Box::new(Saliency::new(img)),
+            Self::BorderMargin { margin } => Box::new(BorderMargin { margin }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn center_bias_and_border_margin() {
+        let center_bias = CenterBias { sigma: 0.25 };
+        assert_eq!(center_bias.weight(0.5, 0.5), 1.0);
+        assert!((center_bias.weight(0.75, 0.5) - (-0.5_f32).exp()).abs() < 1e-6);
+
+        let border_margin = BorderMargin { margin: 0.1 };
+        assert_eq!(border_margin.weight(0.5, 0.5), 1.0);
+        assert_eq!(border_margin.weight(0.05, 0.5), 0.0);
+        assert_eq!(border_margin.weight(0.5, 0.95), 0.0);
+    }
+
+    #[test]
+    fn saliency_prefers_details() {
+        // flat left half, checkered right half.
+        let img = RgbaImage::from_fn(100, 50, |x, y| match x < 50 || (x / 2 + y / 2) % 2 == 0 {
+            true => image::Rgba([40, 40, 40, 255]),
+            false => image::Rgba([220, 220, 220, 255]),
+        });
+
+        let saliency = Saliency::new(&img);
+
+        assert_eq!(saliency.weight(0.05, 0.5), MIN_SALIENCY);
+        assert!(saliency.weight(0.8, 0.5) > 0.5);
+
+        let large_img = imageops::resize(&img, 1000, 500, imageops::FilterType::Nearest);
+        let saliency = Saliency::new(&large_img);
+        assert_eq!(saliency.map.dimensions(), (256, 128));
+        assert!(saliency.weight(0.05, 0.5) < saliency.weight(0.8, 0.5));
+    }
+}
@@ -1 +1,10 @@
+pub mod cluster_count;
+pub mod cluster_params;
+pub mod gmm;
 pub mod histogram_k_means;
+pub mod k_medoids;
+pub mod mean_shift;
+pub mod median_cut;
+pub mod octree;
+pub mod quantizer;
+pub mod wu;
@@ -0,0 +1,345 @@
+use crate::img_utils::{color_metric::ColorMetric, color_point::ColorPoint, histogram::Histogram};
+use crate::kmeans::{cluster_params::ClusterParams, quantizer::Quantization};
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Score used to pick the number of clusters.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionMethod {
+    /// Simplified (centroid based) silhouette, the highest score wins.
+    #[default]
+    Silhouette,
+    /// Elbow of the inertia curve.
+    Elbow,
+    /// Davies–Bouldin index, the lowest score wins.
+    DaviesBouldin,
+}
+
+impl FromStr for SelectionMethod {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "silhouette" => Ok(Self::Silhouette),
+            "elbow" => Ok(Self::Elbow),
+            "davies_bouldin" => Ok(Self::DaviesBouldin),
+            _ => Err(format!("Unknown cluster count method: '{value}'.")),
+        }
+    }
+}
+
+/// Range of cluster counts tried when `number_of_clusters=auto`.
+#[derive(Debug, Clone)]
+pub struct AutoClusterCount {
+    pub min_number_of_clusters: u32,
+    pub max_number_of_clusters: u32,
+    pub method: SelectionMethod,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ClusterCountScore {
+    pub number_of_clusters: u32,
+    pub score: f32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ClusterCountSelection {
+    pub method: SelectionMethod,
+    pub number_of_clusters: u32,
+    pub scores: Vec<ClusterCountScore>,
+}
+
+/// Histogram point with its nearest and second nearest centers.
+struct Assignment {
+    weight: f32,
+    cluster_number: usize,
+    distance: f32,
+    /// Squared distance to the nearest center as the metric defines it.
+    squared_distance: f32,
+    second_distance: f32,
+}
+
+/// Run the quantizer for every cluster count of the range and keep the best scored result.
+pub fn select(
+    histogram: &Histogram,
+    params: &ClusterParams,
+    auto: &AutoClusterCount,
+) -> (Quantization, ClusterCountSelection) {
+    let min = auto.min_number_of_clusters.max(1);
+    let max = auto.max_number_of_clusters.max(min);
+
+    let candidates = (min..=max)
+        .map(|number_of_clusters| {
+            let mut params = params.clone();
+            params.number_of_clusters = number_of_clusters;
+
+            let quantization = params.algorithm.quantizer().quantize(histogram, &params);
+            let score = score(histogram, &params, &quantization.centers, auto.method);
+
+            (number_of_clusters, quantization, score)
+        })
+        .collect::<Vec<_>>();
+
+    let scores = candidates
+        .iter()
+        .map(|(number_of_clusters, _, score)| ClusterCountScore {
+            number_of_clusters: *number_of_clusters,
+            score: *score,
+        })
+        .collect::<Vec<_>>();
+
+    let best_ix = best_index(&scores, auto.method);
+    let (number_of_clusters, quantization, _) = candidates.into_iter().nth(best_ix).unwrap();
+
+    let selection = ClusterCountSelection {
+        method: auto.method,
+        number_of_clusters,
+        scores,
+    };
+
+    (quantization, selection)
+}
+
+fn score(
+    histogram: &Histogram,
+    params: &ClusterParams,
+    centers: &[ColorPoint],
+    method: SelectionMethod,
+) -> f32 {
+    let color_space = params.clustering_color_space();
+    let metric = params.metric.metric();
+
+    let center_coordinates = centers
+        .iter()
+        .filter(|center| center.weight > 0_f32)
+        .map(|center| color_space.coordinates(&center.color))
+        .collect::<Vec<_>>();
+
+    let assignments = histogram
+        .to_vec()
+        .iter()
+        .map(|point| {
+            assign(
+                &color_space.coordinates(&point.color),
+                point.weight,
+                &center_coordinates,
+                metric,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    match method {
+        SelectionMethod::Silhouette => silhouette(&assignments),
+        SelectionMethod::Elbow => inertia(&assignments),
+        SelectionMethod::DaviesBouldin => davies_bouldin(&assignments, &center_coordinates, metric),
+    }
+}
+
+fn assign(
+    coordinates: &[f32],
+    weight: f32,
+    centers: &[Vec<f32>],
+    metric: &dyn ColorMetric,
+) -> Assignment {
+    let mut assignment = Assignment {
+        weight,
+        cluster_number: 0,
+        distance: f32::INFINITY,
+        squared_distance: f32::INFINITY,
+        second_distance: f32::INFINITY,
+    };
+
+    centers
+        .iter()
+        .enumerate()
+        .for_each(|(cluster_number, center)| {
+            let distance = metric.distance(coordinates, center);
+
+            if distance < assignment.distance {
+                assignment.second_distance = assignment.distance;
+                assignment.distance = distance;
+                assignment.cluster_number = cluster_number;
+            } else if distance < assignment.second_distance {
+                assignment.second_distance = distance;
+            }
+        });
+
+    if let Some(center) = centers.get(assignment.cluster_number) {
+        assignment.squared_distance = metric.squared_distance(coordinates, center);
+    }
+
+    assignment
+}
+
+/// Pixel weighted sum of squared distances to the nearest center.
+fn inertia(assignments: &[Assignment]) -> f32 {
+    assignments
+        .iter()
+        .map(|assignment| assignment.weight * assignment.squared_distance)
+        .sum()
+}
+
+/// Pixel weighted simplified silhouette: distances to centers instead of all point pairs.
+fn silhouette(assignments: &[Assignment]) -> f32 {
+    let (sum, total_weight) =
+        assignments
+            .iter()
+            .fold((0_f32, 0_f32), |(sum, total_weight), assignment| {
+                let a = assignment.distance;
+                let b = assignment.second_distance;
+                let s = match b.is_finite() && a.max(b) > 0_f32 {
+                    true => (b - a) / a.max(b),
+                    false => 0_f32,
+                };
+
+                (
+                    sum + s * assignment.weight,
+                    total_weight + assignment.weight,
+                )
+            });
+
+    match total_weight > 0_f32 {
+        true => sum / total_weight,
+        false => 0_f32,
+    }
+}
+
+fn davies_bouldin(
+    assignments: &[Assignment],
+    centers: &[Vec<f32>],
+    metric: &dyn ColorMetric,
+) -> f32 {
+    if centers.len() < 2 {
+        return f32::INFINITY;
+    }
+
+    // mean distance of cluster members to the cluster center.
+    let mut scatter = vec![0_f32; centers.len()];
+    let mut weights = vec![0_f32; centers.len()];
+
+    assignments.iter().for_each(|assignment| {
+        scatter[assignment.cluster_number] += assignment.weight * assignment.distance;
+        weights[assignment.cluster_number] += assignment.weight;
+    });
+
+    scatter
+        .iter_mut()
+        .zip(&weights)
+        .for_each(|(scatter, weight)| {
+            if *weight > 0_f32 {
+                *scatter /= weight;
+            }
+        });
+
+    let sum = (0..centers.len())
+        .map(|i| {
+            (0..centers.len())
+                .filter(|&j| j != i)
+                .map(|j| {
+                    let separation = metric.distance(&centers[i], &centers[j]);
+                    match separation > 0_f32 {
+                        true => (scatter[i] + scatter[j]) / separation,
+                        false => f32::INFINITY,
+                    }
+                })
+                .fold(0_f32, f32::max)
+        })
+        .sum::<f32>();
+
+    sum / centers.len() as f32
+}
+
+fn best_index(scores: &[ClusterCountScore], method: SelectionMethod) -> usize {
+    let values = scores.iter().map(|score| score.score).collect::<Vec<_>>();
+
+    match method {
+        SelectionMethod::Silhouette => arg_best(&values, |a, b| a > b),
+        SelectionMethod::DaviesBouldin => arg_best(&values, |a, b| a < b),
+        SelectionMethod::Elbow => elbow_index(&values),
+    }
+}
+
+fn arg_best(values: &[f32], is_better: impl Fn(f32, f32) -> bool) -> usize {
+    let mut best_ix = 0_usize;
+
+    values.iter().enumerate().for_each(|(i, value)| {
+        if is_better(*value, values[best_ix]) {
+            best_ix = i;
+        }
+    });
+
+    best_ix
+}
+
+/// Point of the inertia curve with the largest distance to the chord
+/// between its first and last points.
+fn elbow_index(inertia: &[f32]) -> usize {
+    let last = inertia.len() - 1;
+
+    if last < 2 {
+        return 0;
+    }
+
+    let (x0, y0) = (0_f32, inertia[0]);
+    let (x1, y1) = (last as f32, inertia[last]);
+    let chord_length = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+
+    if chord_length == 0_f32 {
+        return 0;
+    }
+
+    let distances = inertia
+        .iter()
+        .enumerate()
+        .map(|(i, y)| {
+            ((y1 - y0) * i as f32 - (x1 - x0) * y + x1 * y0 - y1 * x0).abs() / chord_length
+        })
+        .collect::<Vec<_>>();
+
+    arg_best(&distances, |a, b| a > b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_utils::rgb_color::RgbColor;
+
+    #[test]
+    fn select_finds_number_of_color_groups() {
+        let mut histogram = Histogram::new();
+        [(240, 10, 10), (10, 240, 10), (10, 10, 240)]
+            .iter()
+            .for_each(|(r, g, b)| {
+                (0..8_u32).for_each(|i| {
+                    histogram.push_color(&RgbColor::new(r - i, g + i, b + i));
+                });
+            });
+
+        [
+            SelectionMethod::Silhouette,
+            SelectionMethod::Elbow,
+            SelectionMethod::DaviesBouldin,
+        ]
+        .into_iter()
+        .for_each(|method| {
+            let auto = AutoClusterCount {
+                min_number_of_clusters: 2,
+                max_number_of_clusters: 6,
+                method,
+            };
+
+            let (centers, selection) = select(&histogram, &ClusterParams::new(4, 30), &auto);
+
+            assert_eq!(selection.number_of_clusters, 3, "{method:?}");
+            assert_eq!(selection.scores.len(), 5);
+            assert_eq!(centers.centers.len(), 3);
+        });
+    }
+
+    #[test]
+    fn elbow_index_success() {
+        let actual = elbow_index(&[1000.0, 200.0, 150.0, 120.0, 100.0]);
+        assert_eq!(actual, 1);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::img_utils::{
+    color_metric::DistanceMetric, color_point::ColorPoint, color_space::ColorSpace,
+};
+use crate::kmeans::{
+    cluster_count::AutoClusterCount, gmm::GaussianMixture, histogram_k_means::HistogramKMeans,
+    k_medoids::KMedoids, mean_shift::MeanShift, median_cut::MedianCut, octree::Octree,
+    quantizer::Quantizer, wu::Wu,
+};
+use std::str::FromStr;
+
+/// Keeps the clustering time bounded, k-medoids most of all.
+/// Mean-shift returns at most this number of modes.
+pub const NUMBER_OF_CLUSTERS_LIMIT: u32 = 256;
+
+/// How the initial cluster centers are chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InitStrategy {
+    /// First histogram entries get their own clusters, the rest are scattered at random.
+    #[default]
+    Random,
+    /// k-means++ seeding, selection probability is weighted by pixel count.
+    KMeansPlusPlus,
+}
+
+impl FromStr for InitStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "random" => Ok(Self::Random),
+            "kmeans_pp" | "kmeans++" => Ok(Self::KMeansPlusPlus),
+            _ => Err(format!("Unknown init strategy: '{value}'.")),
+        }
+    }
+}
+
+/// How the weight of a base color is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WeightMode {
+    /// Share of distinct histogram colors in the cluster, kept for older consumers.
+    DistinctColors,
+    /// Share of image pixels covered by the cluster.
+    #[default]
+    Pixels,
+}
+
+impl FromStr for WeightMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "distinct_colors" => Ok(Self::DistinctColors),
+            "pixels" => Ok(Self::Pixels),
+            _ => Err(format!("Unknown weight mode: '{value}'.")),
+        }
+    }
+}
+
+impl WeightMode {
+    /// Contribution of a histogram point to the weight of its cluster.
+    pub fn point_weight(self, point: &ColorPoint) -> f32 {
+        match self {
+            Self::DistinctColors => 1_f32,
+            Self::Pixels => point.weight,
+        }
+    }
+}
+
+/// What k-means does with a cluster that lost all its points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyClusterStrategy {
+    /// Move the point farthest from its center into the empty cluster.
+    #[default]
+    ReseedFarthest,
+    /// Split the cluster with the largest pixel count.
+    SplitLargest,
+    /// Remove the cluster, fewer base colors are returned.
+    Drop,
+}
+
+impl FromStr for EmptyClusterStrategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "reseed_farthest" => Ok(Self::ReseedFarthest),
+            "split_largest" => Ok(Self::SplitLargest),
+            "drop" => Ok(Self::Drop),
+            _ => Err(format!("Unknown empty cluster strategy: '{value}'.")),
+        }
+    }
+}
+
+/// Algorithm used to find base colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    KMeans,
+    KMedoids,
+    Gmm,
+    MeanShift,
+    MedianCut,
+    Octree,
+    #[default]
+    Wu,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "kmeans" => Ok(Self::KMeans),
+            "kmedoids" => Ok(Self::KMedoids),
+            "gmm" => Ok(Self::Gmm),
+            "mean_shift" => Ok(Self::MeanShift),
+            "median_cut" => Ok(Self::MedianCut),
+            "octree" => Ok(Self::Octree),
+            "wu" => Ok(Self::Wu),
+            _ => Err(format!("Unknown algorithm: '{value}'.")),
+        }
+    }
+}
+
+impl Algorithm {
+    pub fn quantizer(self) -> &'static dyn Quantizer {
+        match self {
+            Self::KMeans => &HistogramKMeans,
+            Self::KMedoids => &KMedoids,
+            Self::Gmm => &GaussianMixture,
+            Self::MeanShift => &MeanShift,
+            Self::MedianCut => &MedianCut,
+            Self::Octree => &Octree,
+            Self::Wu => &Wu,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterParams {
+    pub algorithm: Algorithm,
+    pub number_of_clusters: u32,
+    pub max_try_count: u32,
+    /// k-means stops when no center moved farther than this distance.
+    pub tolerance: f32,
+    /// Number of k-means restarts with different seeds, the lowest inertia result is kept.
+    pub n_init: u32,
+    pub init_strategy: InitStrategy,
+    pub empty_cluster_strategy: EmptyClusterStrategy,
+    /// Seed for the random number generator, identical seeds give identical results.
+    pub seed: Option<u64>,
+    pub weight_mode: WeightMode,
+    /// Color space in which distances and centers are calculated.
+    pub color_space: ColorSpace,
+    pub metric: DistanceMetric,
+    /// Use the Wu result as initial centers of k-means.
+    pub refine_with_kmeans: bool,
+    /// Pick `number_of_clusters` from a range instead of using the given one.
+    pub auto_cluster_count: Option<AutoClusterCount>,
+    /// Switch k-means to mini-batches for large histograms.
+    pub mini_batch: Option<MiniBatch>,
+    /// Mean-shift kernel radius in the clustering color space, `None` picks a default for the color space.
+    pub bandwidth: Option<f32>,
+    /// Replace base colors by the nearest colors of the image.
+    pub snap_to_existing: bool,
+}
+
+/// Mini-batch k-means settings.
+#[derive(Debug, Clone)]
+pub struct MiniBatch {
+    /// Histograms with more distinct colors are clustered by mini-batches.
+    pub min_histogram_size: usize,
+    /// Number of entries sampled per iteration.
+    pub batch_size: usize,
+}
+
+impl ClusterParams {
+    pub fn new(number_of_clusters: u32, max_try_count: u32) -> Self {
+        Self {
+            algorithm: Algorithm::default(),
+            number_of_clusters,
+            max_try_count,
+            tolerance: 0_f32,
+            n_init: 1,
+            init_strategy: InitStrategy::default(),
+            empty_cluster_strategy: EmptyClusterStrategy::default(),
+            seed: None,
+            weight_mode: WeightMode::default(),
+            color_space: ColorSpace::default(),
+            metric: DistanceMetric::default(),
+            refine_with_kmeans: false,
+            auto_cluster_count: None,
+            mini_batch: None,
+            bandwidth: None,
+            snap_to_existing: false,
+        }
+    }
+
+    /// Color space used for clustering: metrics defined in CIELAB override `color_space`.
+    pub fn clustering_color_space(&self) -> ColorSpace {
+        self.metric
+            .metric()
+            .color_space()
+            .unwrap_or(self.color_space)
+    }
+}
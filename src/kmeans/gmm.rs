@@ -0,0 +1,361 @@
+use crate::img_utils::{color_point::ColorPoint, histogram::Histogram};
+use crate::kmeans::{
+    cluster_params::{ClusterParams, WeightMode},
+    histogram_k_means,
+    quantizer::{Quantization, Quantizer},
+};
+
+/// Gaussian mixture model fitted by expectation maximization.
+/// Components are seeded by k-means and have full covariances in the clustering color space,
+/// the reported spread is the root of the covariance trace.
+pub struct GaussianMixture;
+
+impl Quantizer for GaussianMixture {
+    fn quantize(&self, histogram: &Histogram, params: &ClusterParams) -> Quantization {
+        cluster(histogram, params).into()
+    }
+}
+
+const DIM: usize = 3;
+/// Added to covariance diagonals, keeps components of a single color invertible.
+const REGULARIZATION: f64 = 1e-6;
+/// EM stops when the mean log likelihood per pixel improves less than this.
+const LOG_LIKELIHOOD_TOLERANCE: f64 = 1e-6;
+
+type Matrix = [[f64; DIM]; DIM];
+
+struct Sample {
+    coordinates: [f64; DIM],
+    pixel_count: f64,
+}
+
+#[derive(Clone)]
+struct Component {
+    weight: f64,
+    mean: [f64; DIM],
+    covariance: Matrix,
+}
+
+/// Component prepared for density evaluation.
+struct Gaussian {
+    log_weight: f64,
+    mean: [f64; DIM],
+    inverse: Matrix,
+    log_norm: f64,
+}
+
+impl Gaussian {
+    fn new(component: &Component) -> Option<Self> {
+        let determinant = determinant(&component.covariance);
+
+        if component.weight <= 0_f64 || determinant <= 0_f64 {
+            return None;
+        }
+
+        Some(Self {
+            log_weight: component.weight.ln(),
+            mean: component.mean,
+            inverse: inverse(&component.covariance, determinant),
+            log_norm: -0.5 * (DIM as f64 * (2_f64 * std::f64::consts::PI).ln() + determinant.ln()),
+        })
+    }
+
+    /// Logarithm of the weighted density at the point.
+    fn log_density(&self, coordinates: &[f64; DIM]) -> f64 {
+        let diff: [f64; DIM] = std::array::from_fn(|i| coordinates[i] - self.mean[i]);
+        let mahalanobis = (0..DIM)
+            .map(|i| {
+                (0..DIM)
+                    .map(|j| diff[i] * self.inverse[i][j] * diff[j])
+                    .sum::<f64>()
+            })
+            .sum::<f64>();
+
+        self.log_weight + self.log_norm - 0.5 * mahalanobis
+    }
+}
+
+/// Responsibility weighted sums of a component.
+#[derive(Clone, Default)]
+struct Statistics {
+    pixel_count: f64,
+    distinct_count: f64,
+    sums: [f64; DIM],
+    products: Matrix,
+}
+
+impl Statistics {
+    fn add(&mut self, sample: &Sample, responsibility: f64) {
+        let weight = responsibility * sample.pixel_count;
+
+        self.pixel_count += weight;
+        self.distinct_count += responsibility;
+        (0..DIM).for_each(|i| {
+            self.sums[i] += weight * sample.coordinates[i];
+            (0..DIM).for_each(|j| {
+                self.products[i][j] += weight * sample.coordinates[i] * sample.coordinates[j];
+            });
+        });
+    }
+
+    /// Maximization step, `None` when the component lost all points.
+    fn component(&self, total_pixel_count: f64) -> Option<Component> {
+        if self.pixel_count <= 0_f64 {
+            return None;
+        }
+
+        let mean = self.sums.map(|sum| sum / self.pixel_count);
+        let covariance = std::array::from_fn(|i| {
+            std::array::from_fn(|j| {
+                let value = self.products[i][j] / self.pixel_count - mean[i] * mean[j];
+                match i == j {
+                    true => value.max(0_f64) + REGULARIZATION,
+                    false => value,
+                }
+            })
+        });
+
+        Some(Component {
+            weight: self.pixel_count / total_pixel_count,
+            mean,
+            covariance,
+        })
+    }
+}
+
+/// Calculate base colors as the means of a Gaussian mixture.
+pub fn cluster(histogram: &Histogram, params: &ClusterParams) -> Vec<ColorPoint> {
+    let color_space = params.clustering_color_space();
+
+    let samples = histogram
+        .to_vec()
+        .iter()
+        .map(|point| {
+            let coordinates = color_space.coordinates(&point.color);
+            Sample {
+                coordinates: std::array::from_fn(|i| coordinates[i] as f64),
+                pixel_count: point.weight as f64,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if samples.is_empty() || params.number_of_clusters == 0 {
+        return Vec::new();
+    }
+
+    let total_pixel_count = samples.iter().map(|sample| sample.pixel_count).sum::<f64>();
+
+    // k-means centers, every sample belongs to the nearest one.
+    let means = histogram_k_means::cluster(histogram, params)
+        .centers
+        .iter()
+        .map(|point| {
+            let coordinates = color_space.coordinates(&point.color);
+            std::array::from_fn(|i| coordinates[i] as f64)
+        })
+        .collect::<Vec<[f64; DIM]>>();
+
+    let mut statistics = vec![Statistics::default(); means.len()];
+    samples.iter().for_each(|sample| {
+        let nearest = (0..means.len())
+            .min_by(|&a, &b| {
+                squared_distance(&sample.coordinates, &means[a])
+                    .total_cmp(&squared_distance(&sample.coordinates, &means[b]))
+            })
+            .unwrap_or(0);
+        statistics[nearest].add(sample, 1_f64);
+    });
+
+    let mut components = statistics
+        .iter()
+        .filter_map(|statistics| statistics.component(total_pixel_count))
+        .collect::<Vec<_>>();
+    let mut log_likelihood = f64::NEG_INFINITY;
+
+    for _ in 0..params.max_try_count {
+        let (new_statistics, new_log_likelihood) = expectation(&samples, &components);
+
+        let new_components = new_statistics
+            .iter()
+            .filter_map(|statistics| statistics.component(total_pixel_count))
+            .collect::<Vec<_>>();
+
+        if new_components.is_empty() {
+            break;
+        }
+
+        components = new_components;
+        statistics = new_statistics;
+
+        let improvement = (new_log_likelihood - log_likelihood) / total_pixel_count;
+        log_likelihood = new_log_likelihood;
+
+        if improvement.abs() <= LOG_LIKELIHOOD_TOLERANCE {
+            break;
+        }
+    }
+
+    let total_distinct_count = samples.len() as f64;
+    let distinct_counts = statistics
+        .iter()
+        .filter(|statistics| statistics.pixel_count > 0_f64)
+        .map(|statistics| statistics.distinct_count);
+
+    let mut centers: Vec<ColorPoint> = Vec::new();
+
+    components
+        .iter()
+        .zip(distinct_counts)
+        .for_each(|(component, distinct_count)| {
+            let mean = component.mean.map(|value| value as f32);
+            let weight = match params.weight_mode {
+                WeightMode::Pixels => component.weight,
+                WeightMode::DistinctColors => distinct_count / total_distinct_count,
+            };
+            let spread = (0..DIM)
+                .map(|i| component.covariance[i][i])
+                .sum::<f64>()
+                .sqrt();
+
+            match color_space.to_rgb(&mean) {
+                Ok(color) => centers.push(ColorPoint {
+                    spread: Some(spread as f32),
+                    mixture_weight: Some(component.weight as f32),
+                    ..ColorPoint::new(color, weight as f32)
+                }),
+                Err(err) => println!("Unable to get Rgb color for mixture component. Err: {err}"),
+            }
+        });
+
+    centers
+}
+
+/// Expectation step: responsibilities of every component for every sample,
+/// summed up right away. Returns the sums and the log likelihood.
+fn expectation(samples: &[Sample], components: &[Component]) -> (Vec<Statistics>, f64) {
+    let gaussians = components.iter().map(Gaussian::new).collect::<Vec<_>>();
+    let mut statistics = vec![Statistics::default(); components.len()];
+    let mut log_densities = vec![f64::NEG_INFINITY; components.len()];
+    let mut log_likelihood = 0_f64;
+
+    samples.iter().for_each(|sample| {
+        gaussians
+            .iter()
+            .zip(log_densities.iter_mut())
+            .for_each(|(gaussian, log_density)| {
+                *log_density = gaussian.as_ref().map_or(f64::NEG_INFINITY, |gaussian| {
+                    gaussian.log_density(&sample.coordinates)
+                });
+            });
+
+        let max = log_densities
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        if !max.is_finite() {
+            return;
+        }
+
+        let log_sum = max
+            + log_densities
+                .iter()
+                .map(|log_density| (log_density - max).exp())
+                .sum::<f64>()
+                .ln();
+
+        log_likelihood += sample.pixel_count * log_sum;
+
+        statistics
+            .iter_mut()
+            .zip(&log_densities)
+            .for_each(|(statistics, log_density)| {
+                statistics.add(sample, (log_density - log_sum).exp());
+            });
+    });
+
+    (statistics, log_likelihood)
+}
+
+fn squared_distance(a: &[f64; DIM], b: &[f64; DIM]) -> f64 {
+    (0..DIM).map(|i| (a[i] - b[i]).powi(2)).sum()
+}
+
+fn determinant(m: &Matrix) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// Inverse by the adjugate matrix.
+fn inverse(m: &Matrix, determinant: f64) -> Matrix {
+    std::array::from_fn(|i| {
+        std::array::from_fn(|j| {
+            // cofactor of the transposed position.
+            let (r0, r1) = ((j + 1) % DIM, (j + 2) % DIM);
+            let (c0, c1) = ((i + 1) % DIM, (i + 2) % DIM);
+            (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / determinant
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_utils::rgb_color::RgbColor;
+
+    #[test]
+    fn inverse_success() {
+        let m = [[4.0, 1.0, 0.0], [1.0, 3.0, 1.0], [0.0, 1.0, 2.0]];
+        let inv = inverse(&m, determinant(&m));
+
+        (0..DIM).for_each(|i| {
+            (0..DIM).for_each(|j| {
+                let value = (0..DIM).map(|k| m[i][k] * inv[k][j]).sum::<f64>();
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-12);
+            });
+        });
+    }
+
+    #[test]
+    fn cluster_reports_spread_and_mixture_weights() {
+        let mut histogram = Histogram::new();
+        (0..5_u32).for_each(|i| {
+            // tight red group.
+            (0..3).for_each(|_| histogram.push_color(&RgbColor::new(200 + i, 20, 20)));
+            // wide blue group.
+            (0..5_u32).for_each(|j| {
+                histogram.push_color(&RgbColor::new(20, 20 + i * 10, 160 + j * 10));
+            });
+        });
+
+        let mut params = ClusterParams::new(2, 50);
+        params.seed = Some(1);
+        params.weight_mode = WeightMode::Pixels;
+
+        let centers = cluster(&histogram, &params);
+        assert_eq!(centers.len(), 2);
+
+        let red = centers.iter().find(|point| point.color.r > 150).unwrap();
+        let blue = centers.iter().find(|point| point.color.b > 150).unwrap();
+
+        assert_eq!(red.color.to_vec(), vec![202, 20, 20]);
+        assert_eq!(blue.color.to_vec(), vec![20, 40, 180]);
+        assert!((red.weight - 0.375).abs() < 1e-4);
+        assert!((blue.weight - 0.625).abs() < 1e-4);
+        assert!(red.spread.unwrap() < blue.spread.unwrap());
+        // variance of 200..=204 is 2.
+        assert!((red.spread.unwrap() - 2_f32.sqrt()).abs() < 1e-3);
+
+        // distinct colors weight mode: the weight is the distinct colors share, the mixture weight stays.
+        params.weight_mode = WeightMode::DistinctColors;
+        let centers = cluster(&histogram, &params);
+        let red = centers.iter().find(|point| point.color.r > 150).unwrap();
+        let blue = centers.iter().find(|point| point.color.b > 150).unwrap();
+
+        assert!((red.weight - 5.0 / 30.0).abs() < 1e-4);
+        assert!((red.mixture_weight.unwrap() - 0.375).abs() < 1e-4);
+        assert!((blue.mixture_weight.unwrap() - 0.625).abs() < 1e-4);
+    }
+}
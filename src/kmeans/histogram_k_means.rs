@@ -1,84 +1,243 @@
-use crate::img_utils::{color_point::ColorPoint, histogram::Histogram, rgb_color::RgbColor};
-use rand::prelude::*;
-use std::{
-    cmp::Ordering,
-    collections::{HashMap, HashSet},
+use crate::img_utils::{
+    color_metric::ColorMetric, color_point::ColorPoint, color_space::ColorSpace,
+    histogram::Histogram, rgb_color::RgbColor,
 };
+use crate::kmeans::{
+    cluster_params::{ClusterParams, EmptyClusterStrategy, InitStrategy},
+    quantizer::{Quantization, Quantizer},
+};
+use rand::{distributions::WeightedIndex, prelude::*, rngs::StdRng};
+use serde::Serialize;
+use std::{ops::AddAssign, thread};
+
+/// How the k-means loop ended.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConvergenceStatus {
+    /// No point changed its cluster or centers moved less than `tolerance`,
+    /// mini-batches also stop when the batch inertia no longer improves.
+    Converged,
+    /// Stopped at `max_try_count`.
+    MaxIterations,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ClusterVariance {
+    pub color: RgbColor,
+    /// Pixel weighted mean of squared distances to the cluster center.
+    pub variance: f32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct KMeansDiagnostics {
+    pub status: ConvergenceStatus,
+    /// Centers were found by mini-batch k-means.
+    pub mini_batch: bool,
+    pub iterations: u32,
+    /// Largest center movement of the last iteration.
+    pub center_shift: f32,
+    /// Pixel weighted sum of squared distances to the cluster centers.
+    pub inertia: f32,
+    pub cluster_variances: Vec<ClusterVariance>,
+    /// Number of times an empty cluster was recovered.
+    pub empty_cluster_recoveries: u32,
+    /// Number of empty clusters dropped, fewer base colors than requested are returned.
+    pub dropped_clusters: u32,
+}
 
 struct ClusterEntry {
     histogram_point: ColorPoint,
     cluster_number: u32,
-    weighted_colors: Vec<u32>,
+    /// Color coordinates in the clustering color space.
+    coordinates: Vec<f32>,
+    weighted_coordinates: Vec<f32>,
 }
 
 impl ClusterEntry {
-    pub fn new(histogram_point: &ColorPoint, cluster_number: u32) -> Self {
+    pub fn new(histogram_point: &ColorPoint, color_space: ColorSpace, cluster_number: u32) -> Self {
+        let coordinates = color_space.coordinates(&histogram_point.color);
+
         Self {
             cluster_number,
             histogram_point: histogram_point.clone(),
-            weighted_colors: {
-                histogram_point
-                    .color
-                    .to_vec()
+            weighted_coordinates: {
+                coordinates
                     .iter()
-                    .map(|value| (*value) * histogram_point.weight as u32)
+                    .map(|value| (*value) * histogram_point.weight)
                     .collect::<Vec<_>>()
             },
+            coordinates,
         }
     }
 }
 
+pub struct HistogramKMeans;
+
+impl Quantizer for HistogramKMeans {
+    fn quantize(&self, histogram: &Histogram, params: &ClusterParams) -> Quantization {
+        cluster(histogram, params)
+    }
+}
+
 /// Calculate cluster centers.
-pub fn cluster(
+/// With `params.n_init` above one the clustering is restarted with different seeds
+/// and the result with the lowest inertia is kept.
+pub fn cluster(histogram: &Histogram, params: &ClusterParams) -> Quantization {
+    if params.n_init <= 1 {
+        return run(histogram, params, None);
+    }
+
+    let mut rng = match params.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let seeds = (0..params.n_init)
+        .map(|_| rng.gen::<u64>())
+        .collect::<Vec<_>>();
+
+    let number_of_workers = thread::available_parallelism()
+        .map(|value| value.get())
+        .unwrap_or(1)
+        .min(seeds.len());
+    let chunk_size = seeds.len().div_ceil(number_of_workers);
+
+    let results = thread::scope(|scope| {
+        let workers = seeds
+            .chunks(chunk_size)
+            .map(|seeds| {
+                scope.spawn(move || {
+                    seeds
+                        .iter()
+                        .map(|seed| {
+                            let mut params = params.clone();
+                            params.seed = Some(*seed);
+                            run(histogram, &params, None)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    // results come in seed order, the first of equal inertia wins.
+    results
+        .into_iter()
+        .min_by(|a, b| inertia(a).total_cmp(&inertia(b)))
+        .unwrap_or_default()
+}
+
+fn inertia(quantization: &Quantization) -> f32 {
+    quantization
+        .diagnostics
+        .as_ref()
+        .map_or(f32::INFINITY, |diagnostics| diagnostics.inertia)
+}
+
+/// Calculate cluster centers starting from the given colors instead of `params.init_strategy`.
+pub fn cluster_from_centers(
     histogram: &Histogram,
-    number_of_clusters: u32,
-    max_try_count: u32,
-) -> Vec<ColorPoint> {
-    let mut cluster_data = init_cluster_data(histogram, number_of_clusters as usize);
+    params: &ClusterParams,
+    initial_centers: &[ColorPoint],
+) -> Quantization {
+    run(histogram, params, Some(initial_centers))
+}
 
-    if cluster_data.is_empty() {
-        return Vec::new();
+fn run(
+    histogram: &Histogram,
+    params: &ClusterParams,
+    initial_centers: Option<&[ColorPoint]>,
+) -> Quantization {
+    let number_of_clusters = match initial_centers {
+        Some(centers) => centers.len() as u32,
+        None => params.number_of_clusters,
+    };
+    let metric = params.metric.metric();
+    let color_space = params.clustering_color_space();
+
+    if number_of_clusters == 0 {
+        return Quantization::default();
     }
 
-    let mut cluster_centers = allocate_centers(number_of_clusters);
-    let mut try_counter: u32 = 0_u32;
+    let initial_centers = initial_centers.map(|centers| {
+        centers
+            .iter()
+            .map(|point| color_space.coordinates(&point.color))
+            .collect::<Vec<_>>()
+    });
 
-    while try_counter < max_try_count {
-        match calc_cluster_centers(&cluster_data, number_of_clusters) {
-            Some(new_cluster_centers) => {
-                cluster_centers = new_cluster_centers;
-            }
-            None => break,
-        }
+    let mut rng = match params.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
 
-        match calc_data_clusters(&cluster_data, &cluster_centers) {
-            Some(data_clusters) => {
-                // update cluster numbers.
-                cluster_data.iter_mut().enumerate().for_each(|(i, entry)| {
-                    entry.cluster_number = data_clusters[i];
-                })
-            }
-            None => break,
-        }
+    let mut cluster_data = init_cluster_data(
+        histogram,
+        number_of_clusters as usize,
+        params.init_strategy,
+        initial_centers.as_deref(),
+        color_space,
+        metric,
+        &mut rng,
+    );
 
-        try_counter += 1;
+    if cluster_data.is_empty() {
+        return Quantization::default();
     }
 
+    let use_mini_batch = params
+        .mini_batch
+        .as_ref()
+        .is_some_and(|mini_batch| cluster_data.len() > mini_batch.min_histogram_size);
+
+    let Iterations {
+        cluster_centers,
+        status,
+        iterations,
+        center_shift,
+        empty_clusters,
+    } = match use_mini_batch {
+        true => iterate_mini_batches(&mut cluster_data, number_of_clusters, params, &mut rng),
+        false => iterate(&mut cluster_data, number_of_clusters, params),
+    };
+
+    let squared_distances = calc_cluster_squared_distances(&cluster_data, &cluster_centers, metric);
+    let inertia = squared_distances.iter().sum::<f32>();
+    let mut cluster_variances = Vec::new();
+
     let mut centers: Vec<ColorPoint> = Vec::new();
-    let total_number_of_points = cluster_data.len();
+    let total_weight = cluster_data
+        .iter()
+        .map(|entry| params.weight_mode.point_weight(&entry.histogram_point))
+        .sum::<f32>();
 
     cluster_centers
         .iter()
         .enumerate()
         .for_each(|(cluster_number, center)| {
-            let number_of_points_in_cluster = cluster_data
+            let cluster_weight = cluster_data
                 .iter()
                 .filter(|&entry| entry.cluster_number == cluster_number as u32)
-                .count();
-            let weight = number_of_points_in_cluster as f32 / total_number_of_points as f32;
+                .map(|entry| params.weight_mode.point_weight(&entry.histogram_point))
+                .sum::<f32>();
+            let weight = cluster_weight / total_weight;
 
-            match RgbColor::from_f32_vec(center) {
+            match color_space.to_rgb(center) {
                 Ok(color) => {
+                    let pixel_count = cluster_data
+                        .iter()
+                        .filter(|&entry| entry.cluster_number == cluster_number as u32)
+                        .map(|entry| entry.histogram_point.weight)
+                        .sum::<f32>();
+
+                    cluster_variances.push(ClusterVariance {
+                        color: color.clone(),
+                        variance: squared_distances[cluster_number] / pixel_count,
+                    });
                     centers.push(ColorPoint::new(color, weight));
                 }
                 Err(err) => println!(
@@ -87,126 +246,523 @@ pub fn cluster(
             };
         });
 
-    centers
+    Quantization {
+        centers,
+        diagnostics: Some(KMeansDiagnostics {
+            status,
+            mini_batch: use_mini_batch,
+            iterations,
+            center_shift,
+            inertia,
+            cluster_variances,
+            empty_cluster_recoveries: empty_clusters.recovered,
+            dropped_clusters: empty_clusters.dropped,
+        }),
+    }
 }
 
-fn calc_cluster_centers(
-    cluster_data: &[ClusterEntry],
-    number_of_clusters: u32,
-) -> Option<Vec<Vec<f32>>> {
+/// Result of the k-means iterations.
+struct Iterations {
+    cluster_centers: Vec<Vec<f32>>,
+    status: ConvergenceStatus,
+    iterations: u32,
+    center_shift: f32,
+    empty_clusters: EmptyClusters,
+}
+
+/// Empty clusters met during the iterations.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct EmptyClusters {
+    recovered: u32,
+    dropped: u32,
+}
+
+impl AddAssign for EmptyClusters {
+    fn add_assign(&mut self, other: Self) {
+        self.recovered += other.recovered;
+        self.dropped += other.dropped;
+    }
+}
+
+/// Lloyd iterations over the whole histogram.
+fn iterate(
+    cluster_data: &mut [ClusterEntry],
+    mut number_of_clusters: u32,
+    params: &ClusterParams,
+) -> Iterations {
+    let metric = params.metric.metric();
+    let mut cluster_centers = allocate_centers(number_of_clusters);
+    let mut try_counter: u32 = 0_u32;
+    let mut status = ConvergenceStatus::MaxIterations;
+    let mut empty_clusters = EmptyClusters::default();
+    let mut center_shift = f32::INFINITY;
+
+    while try_counter < params.max_try_count {
+        try_counter += 1;
+
+        empty_clusters += recover_empty_clusters(
+            cluster_data,
+            &mut number_of_clusters,
+            params.empty_cluster_strategy,
+            metric,
+        );
+
+        let new_cluster_centers = calc_cluster_centers(cluster_data, number_of_clusters);
+        center_shift = max_center_shift(&cluster_centers, &new_cluster_centers, metric);
+        cluster_centers = new_cluster_centers;
+
+        // the first centers are compared against zeroed placeholders.
+        if try_counter > 1 && center_shift <= params.tolerance {
+            status = ConvergenceStatus::Converged;
+            break;
+        }
+
+        match calc_data_clusters(cluster_data, &cluster_centers, metric) {
+            Some(data_clusters) => {
+                // update cluster numbers.
+                cluster_data.iter_mut().enumerate().for_each(|(i, entry)| {
+                    entry.cluster_number = data_clusters[i];
+                })
+            }
+            None => {
+                status = ConvergenceStatus::Converged;
+                break;
+            }
+        }
+    }
+
+    // the last assignment may have emptied clusters and moved points, bring centers in line.
+    if status == ConvergenceStatus::MaxIterations {
+        empty_clusters += recover_empty_clusters(
+            cluster_data,
+            &mut number_of_clusters,
+            params.empty_cluster_strategy,
+            metric,
+        );
+
+        let new_cluster_centers = calc_cluster_centers(cluster_data, number_of_clusters);
+        center_shift = max_center_shift(&cluster_centers, &new_cluster_centers, metric);
+        cluster_centers = new_cluster_centers;
+    }
+
+    Iterations {
+        cluster_centers,
+        status,
+        iterations: try_counter,
+        center_shift,
+        empty_clusters,
+    }
+}
+
+/// Mini-batches stop after this many batches without a lower smoothed batch inertia.
+const MINI_BATCH_MAX_NO_IMPROVEMENT: u32 = 10;
+
+/// Mini-batch k-means (Sculley, 2010): every iteration moves the centers towards
+/// a batch of entries sampled by pixel count, the learning rate of a center decays
+/// with the number of samples it got. A single full assignment pass finishes the run.
+/// A single batch hardly ever moves the centers less than `tolerance`, so the loop also stops
+/// once the exponentially smoothed batch inertia no longer improves.
+fn iterate_mini_batches<R: Rng>(
+    cluster_data: &mut [ClusterEntry],
+    mut number_of_clusters: u32,
+    params: &ClusterParams,
+    rng: &mut R,
+) -> Iterations {
+    let metric = params.metric.metric();
+    // a batch larger than the histogram only samples the same entries again.
+    let batch_size = params
+        .mini_batch
+        .as_ref()
+        .map_or(0, |mini_batch| mini_batch.batch_size)
+        .min(cluster_data.len());
+    let mut try_counter: u32 = 0_u32;
+    let mut status = ConvergenceStatus::MaxIterations;
+    let mut center_shift = f32::INFINITY;
+    // smoothing factor of the batch inertia, larger batches weigh more.
+    let smoothing = (2_f32 * batch_size as f32 / (cluster_data.len() + 1) as f32).min(1_f32);
+    let mut smoothed_inertia: Option<f32> = None;
+    let mut best_inertia = f32::INFINITY;
+    let mut no_improvement_count = 0_u32;
+
+    let mut empty_clusters = recover_empty_clusters(
+        cluster_data,
+        &mut number_of_clusters,
+        params.empty_cluster_strategy,
+        metric,
+    );
+
+    let mut cluster_centers = calc_cluster_centers(cluster_data, number_of_clusters);
+    let mut sample_counts = vec![0_f32; cluster_centers.len()];
+
+    let sampler = WeightedIndex::new(
+        cluster_data
+            .iter()
+            .map(|entry| entry.histogram_point.weight),
+    );
+
+    if let Ok(sampler) = sampler {
+        let mut batch = Vec::with_capacity(batch_size);
+
+        while try_counter < params.max_try_count {
+            try_counter += 1;
+
+            batch.clear();
+            batch.extend((0..batch_size).map(|_| {
+                let entry_ix = sampler.sample(rng);
+                let coordinates = &cluster_data[entry_ix].coordinates;
+                (
+                    entry_ix,
+                    nearest_center(coordinates, &cluster_centers, metric),
+                )
+            }));
+
+            let batch_inertia = batch
+                .iter()
+                .map(|(entry_ix, cluster_number)| {
+                    metric.squared_distance(
+                        &cluster_data[*entry_ix].coordinates,
+                        &cluster_centers[*cluster_number],
+                    )
+                })
+                .sum::<f32>()
+                / batch_size.max(1) as f32;
+
+            let previous_centers = cluster_centers.clone();
+
+            batch.iter().for_each(|(entry_ix, cluster_number)| {
+                sample_counts[*cluster_number] += 1_f32;
+                let learning_rate = 1_f32 / sample_counts[*cluster_number];
+
+                cluster_centers[*cluster_number]
+                    .iter_mut()
+                    .zip(&cluster_data[*entry_ix].coordinates)
+                    .for_each(|(center, value)| {
+                        *center += learning_rate * (value - *center);
+                    });
+            });
+
+            center_shift = max_center_shift(&previous_centers, &cluster_centers, metric);
+
+            if center_shift <= params.tolerance {
+                status = ConvergenceStatus::Converged;
+                break;
+            }
+
+            let inertia = smoothed_inertia.map_or(batch_inertia, |smoothed| {
+                smoothed + smoothing * (batch_inertia - smoothed)
+            });
+            smoothed_inertia = Some(inertia);
+
+            match inertia < best_inertia {
+                true => {
+                    best_inertia = inertia;
+                    no_improvement_count = 0;
+                }
+                false => no_improvement_count += 1,
+            }
+
+            if no_improvement_count >= MINI_BATCH_MAX_NO_IMPROVEMENT {
+                status = ConvergenceStatus::Converged;
+                break;
+            }
+        }
+    }
+
+    let data_clusters = nearest_cluster_numbers(cluster_data, &cluster_centers, metric);
+    cluster_data
+        .iter_mut()
+        .zip(data_clusters)
+        .for_each(|(entry, cluster_number)| entry.cluster_number = cluster_number);
+
+    empty_clusters += recover_empty_clusters(
+        cluster_data,
+        &mut number_of_clusters,
+        params.empty_cluster_strategy,
+        metric,
+    );
+
+    cluster_centers = calc_cluster_centers(cluster_data, number_of_clusters);
+
+    Iterations {
+        cluster_centers,
+        status,
+        iterations: try_counter,
+        center_shift,
+        empty_clusters,
+    }
+}
+
+fn calc_cluster_centers(cluster_data: &[ClusterEntry], number_of_clusters: u32) -> Vec<Vec<f32>> {
     // Sum cluster weights.
     // And accumulate cluster centers sum.
-    let mut cluster_weights = HashMap::new();
+    let (cluster_weights, mut cluster_centers) =
+        accumulate_clusters(cluster_data, number_of_clusters);
+
+    // Mean cluster centers.
+    cluster_centers.iter_mut().zip(&cluster_weights).for_each(
+        |(cluster_center, cluster_weight)| {
+            if *cluster_weight > 0_f32 {
+                cluster_center.iter_mut().for_each(|center| {
+                    *center /= cluster_weight;
+                });
+            }
+        },
+    );
+
+    cluster_centers
+}
+
+/// Sums of weights and weighted coordinates for every cluster.
+fn accumulate_clusters(
+    cluster_data: &[ClusterEntry],
+    number_of_clusters: u32,
+) -> (Vec<f32>, Vec<Vec<f32>>) {
+    let mut cluster_weights = vec![0_f32; number_of_clusters as usize];
     let mut cluster_centers = allocate_centers(number_of_clusters);
 
     cluster_data.iter().for_each(|cluster_entry| {
-        let cluster_number = &cluster_entry.cluster_number;
-
-        // Sum cluster weights.
-        let cluster_weight = cluster_weights.entry(cluster_number).or_insert(0_f32);
-        *cluster_weight += &cluster_entry.histogram_point.weight;
+        let cluster_number = cluster_entry.cluster_number as usize;
 
-        // Accumulate cluster centers sum.
-        let cluster_center = &mut cluster_centers[*cluster_number as usize];
-        cluster_center
+        cluster_weights[cluster_number] += cluster_entry.histogram_point.weight;
+        cluster_centers[cluster_number]
             .iter_mut()
-            .enumerate()
-            .for_each(|(i, center)| {
-                *center += cluster_entry.weighted_colors[i] as f32;
+            .zip(&cluster_entry.weighted_coordinates)
+            .for_each(|(center, value)| {
+                *center += value;
             });
     });
 
-    // Mean cluster centers.
-    cluster_centers
-        .iter_mut()
-        .enumerate()
-        .for_each(|(cluster_number, cluster_center)| {
-            if let Some(cluster_weight) = cluster_weights.get(&(cluster_number as u32)) {
-                {
-                    cluster_center.iter_mut().for_each(|center| {
-                        *center /= cluster_weight;
-                    });
-                }
-            }
-        });
-
-    Some(cluster_centers)
+    (cluster_weights, cluster_centers)
 }
 
-fn calc_data_clusters(
+fn calc_data_clusters<M: ColorMetric + ?Sized>(
     cluster_data: &[ClusterEntry],
     cluster_centers: &[Vec<f32>],
+    metric: &M,
 ) -> Option<Vec<u32>> {
-    let mut new_data_clusters = cluster_data
+    // pick up cluster with minimum distance to each point.
+    let new_data_clusters = cluster_data
         .iter()
-        .map(|entry| entry.cluster_number)
+        .map(|entry| nearest_center(&entry.coordinates, cluster_centers, metric) as u32)
         .collect::<Vec<_>>();
-    let mut changed = false;
-    let mut distances = vec![0_f32; cluster_centers.len()];
 
-    cluster_data
+    let changed = cluster_data
+        .iter()
+        .zip(&new_data_clusters)
+        .any(|(entry, cluster_number)| entry.cluster_number != *cluster_number);
+
+    if !changed {
+        return None;
+    }
+
+    Some(new_data_clusters)
+}
+
+/// Index of the nearest center, the first one wins on ties.
+fn nearest_center<M: ColorMetric + ?Sized>(
+    coordinates: &[f32],
+    cluster_centers: &[Vec<f32>],
+    metric: &M,
+) -> usize {
+    cluster_centers
         .iter()
         .enumerate()
-        .for_each(|(entry_ix, entry)| {
-            // for each point we calc distances to each cluster center.
-            cluster_centers
-                .iter()
-                .enumerate()
-                .for_each(|(cluster_number, center)| {
-                    distances[cluster_number] =
-                        distance(&entry.histogram_point.color.to_f32_vec(), center);
-                });
+        .fold(
+            (0_usize, f32::INFINITY),
+            |(best_ix, best_distance), (cluster_number, center)| {
+                let distance = metric.distance(coordinates, center);
+                match distance < best_distance {
+                    true => (cluster_number, distance),
+                    false => (best_ix, best_distance),
+                }
+            },
+        )
+        .0
+}
+
+/// Fix clusters without points, clusters which can't be refilled are dropped.
+fn recover_empty_clusters<M: ColorMetric + ?Sized>(
+    cluster_data: &mut [ClusterEntry],
+    number_of_clusters: &mut u32,
+    strategy: EmptyClusterStrategy,
+    metric: &M,
+) -> EmptyClusters {
+    let mut empty_clusters = EmptyClusters::default();
+
+    loop {
+        let sizes = cluster_sizes(cluster_data, *number_of_clusters);
+
+        let empty_cluster = match sizes.iter().position(|size| *size == 0) {
+            Some(cluster_number) => cluster_number as u32,
+            None => break,
+        };
 
-            // pick up cluster with minimum distance to it.
-            let new_cluster_number = min_distance_index(&distances);
+        let centers = calc_cluster_centers(cluster_data, *number_of_clusters);
 
-            // apply new cluster number to point.
-            if entry.cluster_number != new_cluster_number as u32 {
-                // note cluster changed.
-                changed = true;
-                new_data_clusters[entry_ix] = new_cluster_number as u32;
+        let reseeded = match strategy {
+            EmptyClusterStrategy::ReseedFarthest => {
+                reseed_from_farthest(cluster_data, &centers, &sizes, empty_cluster, metric)
             }
-        });
+            EmptyClusterStrategy::SplitLargest => {
+                split_largest(cluster_data, &centers, &sizes, empty_cluster, metric)
+            }
+            EmptyClusterStrategy::Drop => false,
+        };
 
-    if !changed {
-        return None;
+        // nothing left to move into the cluster.
+        match reseeded {
+            true => empty_clusters.recovered += 1,
+            false => {
+                drop_cluster(cluster_data, number_of_clusters, empty_cluster);
+                empty_clusters.dropped += 1;
+            }
+        }
     }
 
-    // calc new number of clusters in data.
-    let counts: HashSet<&u32> = HashSet::from_iter(new_data_clusters.iter());
+    empty_clusters
+}
 
-    // if lost any cluster then return None.
-    if counts.len() < cluster_centers.len() {
-        return None;
-    }
+fn cluster_sizes(cluster_data: &[ClusterEntry], number_of_clusters: u32) -> Vec<usize> {
+    let mut sizes = vec![0_usize; number_of_clusters as usize];
 
-    Some(new_data_clusters)
+    cluster_data
+        .iter()
+        .for_each(|entry| sizes[entry.cluster_number as usize] += 1);
+
+    sizes
 }
 
-fn distance(point_a: &[f32], point_b: &[f32]) -> f32 {
-    let mut sum_squared_diffs = 0_f32;
+/// Move the point farthest from its center into the empty cluster.
+fn reseed_from_farthest<M: ColorMetric + ?Sized>(
+    cluster_data: &mut [ClusterEntry],
+    centers: &[Vec<f32>],
+    sizes: &[usize],
+    empty_cluster: u32,
+    metric: &M,
+) -> bool {
+    let farthest = cluster_data
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| sizes[entry.cluster_number as usize] > 1)
+        .map(|(i, entry)| {
+            let center = &centers[entry.cluster_number as usize];
+            (i, metric.distance(&entry.coordinates, center))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    match farthest {
+        Some((i, _)) => {
+            cluster_data[i].cluster_number = empty_cluster;
+            true
+        }
+        None => false,
+    }
+}
 
-    point_a.iter().enumerate().for_each(|(i, _)| {
-        sum_squared_diffs += (point_a[i] - point_b[i]).powf(2_f32);
+/// Split the cluster with the largest pixel count: its farthest point becomes a new seed
+/// and takes the points which are closer to it than to the old center.
+fn split_largest<M: ColorMetric + ?Sized>(
+    cluster_data: &mut [ClusterEntry],
+    centers: &[Vec<f32>],
+    sizes: &[usize],
+    empty_cluster: u32,
+    metric: &M,
+) -> bool {
+    let mut cluster_weights = vec![0_f32; sizes.len()];
+    cluster_data.iter().for_each(|entry| {
+        cluster_weights[entry.cluster_number as usize] += entry.histogram_point.weight;
     });
 
-    sum_squared_diffs.sqrt()
-}
+    let largest = cluster_weights
+        .iter()
+        .enumerate()
+        .filter(|(cluster_number, _)| sizes[*cluster_number] > 1)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(cluster_number, _)| cluster_number as u32);
 
-fn min_distance_index(values: &[f32]) -> usize {
-    let mut index_of_min_value: usize = 0;
-    let mut small_value = values[index_of_min_value];
+    let largest = match largest {
+        Some(cluster_number) => cluster_number,
+        None => return false,
+    };
 
-    values.iter().enumerate().for_each(|(i, value)| {
-        if value.total_cmp(&small_value) == Ordering::Less {
-            {
-                small_value = *value;
-                index_of_min_value = i;
-            }
+    let center = &centers[largest as usize];
+
+    let distance_to_center = |entry: &ClusterEntry| metric.distance(&entry.coordinates, center);
+
+    let seed_ix = cluster_data
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.cluster_number == largest)
+        .max_by(|(_, a), (_, b)| distance_to_center(a).total_cmp(&distance_to_center(b)))
+        .map(|(i, _)| i)
+        .unwrap();
+    let seed = &cluster_data[seed_ix].coordinates;
+
+    let mut moved = cluster_data
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.cluster_number == largest)
+        .filter(|(_, entry)| metric.distance(&entry.coordinates, seed) < distance_to_center(entry))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    // both halves must keep points.
+    if moved.is_empty() || moved.len() == sizes[largest as usize] {
+        moved = vec![seed_ix];
+    }
+
+    moved
+        .iter()
+        .for_each(|&i| cluster_data[i].cluster_number = empty_cluster);
+
+    true
+}
+
+fn drop_cluster(cluster_data: &mut [ClusterEntry], number_of_clusters: &mut u32, cluster: u32) {
+    cluster_data.iter_mut().for_each(|entry| {
+        if entry.cluster_number > cluster {
+            entry.cluster_number -= 1;
         }
     });
 
-    index_of_min_value
+    *number_of_clusters -= 1;
+}
+
+/// Largest distance between old and new positions of the centers.
+fn max_center_shift<M: ColorMetric + ?Sized>(
+    old_centers: &[Vec<f32>],
+    new_centers: &[Vec<f32>],
+    metric: &M,
+) -> f32 {
+    // a cluster was dropped, centers can't be matched.
+    if old_centers.len() != new_centers.len() {
+        return f32::INFINITY;
+    }
+
+    old_centers
+        .iter()
+        .zip(new_centers)
+        // the shift is a plain distance, also for metrics reporting squared distances.
+        .map(|(old_center, new_center)| metric.squared_distance(old_center, new_center).sqrt())
+        .fold(0_f32, f32::max)
+}
+
+/// Pixel weighted sums of squared distances to the center for every cluster.
+fn calc_cluster_squared_distances<M: ColorMetric + ?Sized>(
+    cluster_data: &[ClusterEntry],
+    cluster_centers: &[Vec<f32>],
+    metric: &M,
+) -> Vec<f32> {
+    let mut sums = vec![0_f32; cluster_centers.len()];
+
+    cluster_data.iter().for_each(|entry| {
+        let cluster_number = entry.cluster_number as usize;
+        let squared_distance =
+            metric.squared_distance(&entry.coordinates, &cluster_centers[cluster_number]);
+        sums[cluster_number] += entry.histogram_point.weight * squared_distance;
+    });
+
+    sums
 }
 
 fn allocate_centers(num_clusters: u32) -> Vec<Vec<f32>> {
@@ -214,65 +770,456 @@ fn allocate_centers(num_clusters: u32) -> Vec<Vec<f32>> {
     vec![vec![0_f32; num_dimentions]; num_clusters as usize]
 }
 
-fn init_cluster_data(histogram: &Histogram, number_of_clusters: usize) -> Vec<ClusterEntry> {
+fn init_cluster_data<M: ColorMetric + ?Sized, R: Rng>(
+    histogram: &Histogram,
+    number_of_clusters: usize,
+    init_strategy: InitStrategy,
+    initial_centers: Option<&[Vec<f32>]>,
+    color_space: ColorSpace,
+    metric: &M,
+    rng: &mut R,
+) -> Vec<ClusterEntry> {
     let vec = histogram.to_vec();
 
     if vec.is_empty() {
         return Vec::new();
     }
 
-    let mut rng = rand::thread_rng();
-
-    let cluster_data = vec
+    let mut cluster_data = vec
         .iter()
-        .enumerate()
-        .map(|(i, color_point)| {
+        .map(|color_point| ClusterEntry::new(color_point, color_space, 0))
+        .collect::<Vec<_>>();
+
+    let cluster_numbers = match (initial_centers, init_strategy) {
+        (Some(centers), _) => nearest_cluster_numbers(&cluster_data, centers, metric),
+        (None, InitStrategy::Random) => random_cluster_numbers(vec.len(), number_of_clusters, rng),
+        (None, InitStrategy::KMeansPlusPlus) => {
+            let centers = kmeans_plus_plus_centers(&cluster_data, number_of_clusters, metric, rng);
+            nearest_cluster_numbers(&cluster_data, &centers, metric)
+        }
+    };
+
+    cluster_data
+        .iter_mut()
+        .zip(cluster_numbers)
+        .for_each(|(entry, cluster_number)| entry.cluster_number = cluster_number);
+
+    cluster_data
+}
+
+fn random_cluster_numbers<R: Rng>(
+    number_of_points: usize,
+    number_of_clusters: usize,
+    rng: &mut R,
+) -> Vec<u32> {
+    (0..number_of_points)
+        .map(|i| {
             let cluster_number = match i < number_of_clusters {
                 true => i,
                 false => rng.gen_range(0..number_of_clusters),
             };
 
-            ClusterEntry::new(color_point, cluster_number as u32)
+            cluster_number as u32
         })
+        .collect()
+}
+
+/// k-means++ seeding: every next center is picked with probability
+/// proportional to pixel count multiplied by the squared distance to the nearest chosen center.
+fn kmeans_plus_plus_centers<M: ColorMetric + ?Sized, R: Rng>(
+    cluster_data: &[ClusterEntry],
+    number_of_clusters: usize,
+    metric: &M,
+    rng: &mut R,
+) -> Vec<Vec<f32>> {
+    let pixel_counts = cluster_data
+        .iter()
+        .map(|entry| entry.histogram_point.weight)
         .collect::<Vec<_>>();
 
+    let mut centers: Vec<Vec<f32>> = Vec::with_capacity(number_of_clusters);
+    let mut min_squared_distances = vec![f32::INFINITY; cluster_data.len()];
+
+    while centers.len() < number_of_clusters {
+        let candidate_weights = match centers.is_empty() {
+            true => pixel_counts.clone(),
+            false => pixel_counts
+                .iter()
+                .zip(&min_squared_distances)
+                .map(|(count, squared_distance)| count * squared_distance)
+                .collect(),
+        };
+
+        // all points already coincide with centers, fall back to pixel counts.
+        let index = match WeightedIndex::new(&candidate_weights) {
+            Ok(dist) => dist.sample(rng),
+            Err(_) => match WeightedIndex::new(&pixel_counts) {
+                Ok(dist) => dist.sample(rng),
+                Err(_) => rng.gen_range(0..cluster_data.len()),
+            },
+        };
+
+        let center = cluster_data[index].coordinates.clone();
+
+        cluster_data.iter().enumerate().for_each(|(i, entry)| {
+            let squared_distance = metric.squared_distance(&entry.coordinates, &center);
+            if squared_distance < min_squared_distances[i] {
+                min_squared_distances[i] = squared_distance;
+            }
+        });
+
+        centers.push(center);
+    }
+
+    centers
+}
+
+fn nearest_cluster_numbers<M: ColorMetric + ?Sized>(
+    cluster_data: &[ClusterEntry],
+    centers: &[Vec<f32>],
+    metric: &M,
+) -> Vec<u32> {
     cluster_data
+        .iter()
+        .map(|entry| nearest_center(&entry.coordinates, centers, metric) as u32)
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::img_utils::color_metric::{DistanceMetric, Euclidean};
+    use crate::kmeans::cluster_params::{MiniBatch, WeightMode};
+    use std::collections::HashSet;
 
     #[test]
-    fn distance_success() {
-        let point_a = vec![1.0; 4];
-        let point_b = vec![3.0; 4];
-        let actual = distance(&point_a, &point_b);
-        assert_eq!(actual, 4.0);
-
-        let point_a = vec![-1.0; 4];
-        let point_b = vec![-3.0; 4];
-        let actual = distance(&point_a, &point_b);
-        assert_eq!(actual, 4.0);
-
-        let point_a = vec![-1.0; 4];
-        let point_b = vec![1.0; 4];
-        let actual = distance(&point_a, &point_b);
-        assert_eq!(actual, 4.0);
-    }
+    fn nearest_center_success() {
+        let centers = |values: [f32; 4]| values.map(|value| vec![value]).to_vec();
 
-    #[test]
-    fn min_distance_index_success() {
-        let values = vec![1.0, 2.0, 3.0, 4.0];
-        let actual = min_distance_index(&values);
+        let actual = nearest_center(&[0.0], &centers([1.0, 2.0, 3.0, 4.0]), &Euclidean);
         assert_eq!(actual, 0);
 
-        let values = vec![2.0, 3.0, 4.0, 1.0];
-        let actual = min_distance_index(&values);
+        let actual = nearest_center(&[0.0], &centers([2.0, 3.0, 4.0, 1.0]), &Euclidean);
         assert_eq!(actual, 3);
 
-        let values = vec![2.0, 3.0, 1.0, 4.0];
-        let actual = min_distance_index(&values);
+        let actual = nearest_center(&[0.0], &centers([2.0, 3.0, 1.0, 4.0]), &Euclidean);
         assert_eq!(actual, 2);
     }
+
+    #[test]
+    fn kmeans_plus_plus_centers_picks_distinct_colors() {
+        let cluster_data = [
+            ColorPoint::new(RgbColor::new(0, 0, 0), 100.0),
+            ColorPoint::new(RgbColor::new(1, 1, 1), 100.0),
+            ColorPoint::new(RgbColor::new(255, 255, 255), 1.0),
+        ]
+        .iter()
+        .map(|point| ClusterEntry::new(point, ColorSpace::Srgb, 0))
+        .collect::<Vec<_>>();
+        let mut rng = rand::thread_rng();
+
+        (0..20).for_each(|_| {
+            let centers = kmeans_plus_plus_centers(&cluster_data, 3, &Euclidean, &mut rng);
+            assert_eq!(centers.len(), 3);
+
+            let distinct = centers
+                .iter()
+                .map(|center| RgbColor::from_f32_vec(center).unwrap().packed_key())
+                .collect::<HashSet<_>>();
+            assert_eq!(distinct.len(), 3);
+        });
+    }
+
+    #[test]
+    fn cluster_with_seed_is_deterministic() {
+        let mut histogram = Histogram::new();
+        (0..64_u32).for_each(|i| {
+            histogram.push_color(&RgbColor::new(i * 4, 255 - i * 3, (i * 37) % 256));
+        });
+
+        [InitStrategy::Random, InitStrategy::KMeansPlusPlus]
+            .into_iter()
+            .for_each(|init_strategy| {
+                let mut params = ClusterParams::new(4, 30);
+                params.init_strategy = init_strategy;
+                params.seed = Some(42);
+
+                let first = format!("{:?}", cluster(&histogram, &params).centers);
+                let second = format!("{:?}", cluster(&histogram, &params).centers);
+                assert_eq!(first, second);
+            });
+    }
+
+    #[test]
+    fn cluster_weight_modes() {
+        let mut histogram = Histogram::new();
+        (0..90).for_each(|_| histogram.push_color(&RgbColor::new(0, 0, 255)));
+        (0..10_u32).for_each(|i| histogram.push_color(&RgbColor::new(255, 200 + i, 0)));
+
+        let mut params = ClusterParams::new(2, 30);
+        params.init_strategy = InitStrategy::KMeansPlusPlus;
+        params.seed = Some(7);
+
+        let weight_of_blue = |centers: Vec<ColorPoint>| {
+            centers
+                .iter()
+                .find(|point| point.color.b == 255)
+                .map(|point| point.weight)
+                .unwrap()
+        };
+
+        params.weight_mode = WeightMode::Pixels;
+        assert_eq!(weight_of_blue(cluster(&histogram, &params).centers), 0.9);
+
+        params.weight_mode = WeightMode::DistinctColors;
+        assert_eq!(
+            weight_of_blue(cluster(&histogram, &params).centers),
+            1.0 / 11.0
+        );
+    }
+
+    #[test]
+    fn cluster_in_perceptual_color_spaces() {
+        let mut histogram = Histogram::new();
+        (0..20_u32).for_each(|i| {
+            histogram.push_color(&RgbColor::new(200 + i, 10, 10));
+            histogram.push_color(&RgbColor::new(10, 10, 200 + i));
+        });
+
+        [ColorSpace::LinearRgb, ColorSpace::Lab, ColorSpace::Oklab]
+            .into_iter()
+            .for_each(|color_space| {
+                let mut params = ClusterParams::new(2, 30);
+                params.init_strategy = InitStrategy::KMeansPlusPlus;
+                params.seed = Some(1);
+                params.color_space = color_space;
+
+                let centers = cluster(&histogram, &params).centers;
+                assert_eq!(centers.len(), 2);
+                assert!(centers.iter().any(|point| point.color.r > 200));
+                assert!(centers.iter().any(|point| point.color.b > 200));
+            });
+    }
+
+    #[test]
+    fn cluster_with_metrics() {
+        let mut histogram = Histogram::new();
+        (0..20_u32).for_each(|i| {
+            histogram.push_color(&RgbColor::new(200 + i, 10, 10));
+            histogram.push_color(&RgbColor::new(10, 200 + i, 10));
+        });
+
+        [
+            DistanceMetric::SquaredEuclidean,
+            DistanceMetric::Manhattan,
+            DistanceMetric::Cie76,
+            DistanceMetric::Cie94,
+            DistanceMetric::Ciede2000,
+        ]
+        .into_iter()
+        .for_each(|metric| {
+            let mut params = ClusterParams::new(2, 30);
+            params.init_strategy = InitStrategy::KMeansPlusPlus;
+            params.seed = Some(3);
+            params.metric = metric;
+
+            let centers = cluster(&histogram, &params).centers;
+            assert!(
+                centers.iter().any(|point| point.color.r > 200),
+                "{metric:?}"
+            );
+            assert!(
+                centers.iter().any(|point| point.color.g > 200),
+                "{metric:?}"
+            );
+        });
+    }
+
+    #[test]
+    fn recover_empty_clusters_strategies() {
+        let points = [
+            ColorPoint::new(RgbColor::new(0, 0, 0), 5.0),
+            ColorPoint::new(RgbColor::new(10, 10, 10), 5.0),
+            ColorPoint::new(RgbColor::new(250, 250, 250), 1.0),
+        ];
+
+        [
+            (EmptyClusterStrategy::ReseedFarthest, 2, 1, 0),
+            (EmptyClusterStrategy::SplitLargest, 2, 1, 0),
+            (EmptyClusterStrategy::Drop, 1, 0, 1),
+        ]
+        .into_iter()
+        .for_each(|(strategy, expected_clusters, recovered, dropped)| {
+            let mut cluster_data = points
+                .iter()
+                .map(|point| ClusterEntry::new(point, ColorSpace::Srgb, 0))
+                .collect::<Vec<_>>();
+            let mut number_of_clusters = 2;
+
+            let empty_clusters = recover_empty_clusters(
+                &mut cluster_data,
+                &mut number_of_clusters,
+                strategy,
+                &Euclidean,
+            );
+
+            assert_eq!(empty_clusters, EmptyClusters { recovered, dropped });
+            assert_eq!(number_of_clusters, expected_clusters, "{strategy:?}");
+            assert!(cluster_sizes(&cluster_data, number_of_clusters)
+                .iter()
+                .all(|size| *size > 0));
+        });
+
+        // the farthest point starts the new cluster.
+        let mut cluster_data = points
+            .iter()
+            .map(|point| ClusterEntry::new(point, ColorSpace::Srgb, 0))
+            .collect::<Vec<_>>();
+        let mut number_of_clusters = 2;
+        recover_empty_clusters(
+            &mut cluster_data,
+            &mut number_of_clusters,
+            EmptyClusterStrategy::ReseedFarthest,
+            &Euclidean,
+        );
+        assert_eq!(cluster_data[2].cluster_number, 1);
+    }
+
+    #[test]
+    fn cluster_reports_diagnostics() {
+        let mut histogram = Histogram::new();
+        histogram.push_color(&RgbColor::new(0, 0, 0));
+        histogram.push_color(&RgbColor::new(128, 128, 128));
+        histogram.push_color(&RgbColor::new(255, 255, 255));
+
+        let quantization = cluster(&histogram, &ClusterParams::new(5, 30));
+        let diagnostics = quantization.diagnostics.unwrap();
+
+        assert_eq!(quantization.centers.len(), 3);
+        assert_eq!(diagnostics.status, ConvergenceStatus::Converged);
+        // three colors can't fill five clusters, the empty ones are dropped.
+        assert_eq!(diagnostics.empty_cluster_recoveries, 0);
+        assert_eq!(diagnostics.dropped_clusters, 2);
+        assert_eq!(diagnostics.inertia, 0.0);
+        assert_eq!(diagnostics.cluster_variances.len(), 3);
+    }
+
+    #[test]
+    fn squared_euclidean_matches_euclidean() {
+        let mut histogram = Histogram::new();
+        (0..50_u32).for_each(|i| {
+            histogram.push_color(&RgbColor::new(i * 5, 255 - i * 5, (i * 91) % 256));
+        });
+
+        let diagnostics = |metric| {
+            let mut params = ClusterParams::new(4, 100);
+            params.init_strategy = InitStrategy::KMeansPlusPlus;
+            params.seed = Some(11);
+            params.tolerance = 2.0;
+            params.metric = metric;
+
+            cluster(&histogram, &params).diagnostics.unwrap()
+        };
+
+        let euclidean = diagnostics(DistanceMetric::Euclidean);
+        let squared_euclidean = diagnostics(DistanceMetric::SquaredEuclidean);
+
+        // same seeding, same stop and inertia in squared units, not in squared squares.
+        assert_eq!(squared_euclidean.iterations, euclidean.iterations);
+        assert!((squared_euclidean.center_shift - euclidean.center_shift).abs() < 1e-3);
+        assert!((squared_euclidean.inertia / euclidean.inertia - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cluster_stops_at_tolerance() {
+        let mut histogram = Histogram::new();
+        (0..50_u32).for_each(|i| {
+            histogram.push_color(&RgbColor::new(i * 5, 255 - i * 5, (i * 91) % 256));
+        });
+
+        let mut params = ClusterParams::new(4, 100);
+        params.seed = Some(11);
+
+        let exact = cluster(&histogram, &params).diagnostics.unwrap();
+
+        params.tolerance = 1000.0;
+        let loose = cluster(&histogram, &params).diagnostics.unwrap();
+
+        assert_eq!(loose.status, ConvergenceStatus::Converged);
+        assert_eq!(loose.iterations, 2);
+        assert!(loose.center_shift <= 1000.0);
+        assert!(exact.iterations >= loose.iterations);
+        assert!(exact.inertia > 0.0);
+        assert_eq!(
+            exact.cluster_variances.len(),
+            cluster(&histogram, &params).centers.len()
+        );
+    }
+
+    #[test]
+    fn cluster_by_mini_batches() {
+        let mut histogram = Histogram::new();
+        [(240, 20, 20), (20, 240, 20), (20, 20, 240)]
+            .iter()
+            .for_each(|(r, g, b)| {
+                (0..100_u32).for_each(|i| {
+                    histogram.push_color(&RgbColor::new(r - i % 10, g + i / 10, *b));
+                });
+            });
+
+        let mut params = ClusterParams::new(3, 30);
+        params.seed = Some(5);
+        params.init_strategy = InitStrategy::KMeansPlusPlus;
+        let full = cluster(&histogram, &params);
+
+        params.mini_batch = Some(MiniBatch {
+            min_histogram_size: 100,
+            batch_size: 32,
+        });
+        let mini_batch = cluster(&histogram, &params);
+
+        let full_diagnostics = full.diagnostics.unwrap();
+        let diagnostics = mini_batch.diagnostics.unwrap();
+        assert!(!full_diagnostics.mini_batch);
+        assert!(diagnostics.mini_batch);
+        assert!(diagnostics.inertia <= full_diagnostics.inertia * 1.05);
+
+        assert_eq!(mini_batch.centers.len(), 3);
+        mini_batch
+            .centers
+            .iter()
+            .for_each(|point| assert!((point.weight - 1.0 / 3.0).abs() < 1e-6));
+
+        // without a tolerance the batch inertia stops the loop.
+        params.max_try_count = 100_000;
+        let diagnostics = cluster(&histogram, &params).diagnostics.unwrap();
+        assert_eq!(diagnostics.status, ConvergenceStatus::Converged);
+        assert!(diagnostics.iterations < 1000, "{}", diagnostics.iterations);
+    }
+
+    #[test]
+    fn cluster_keeps_best_restart() {
+        let mut histogram = Histogram::new();
+        (0..60_u32).for_each(|i| {
+            histogram.push_color(&RgbColor::new((i * 37) % 256, (i * 101) % 256, i * 4));
+        });
+
+        let mut params = ClusterParams::new(5, 30);
+        params.seed = Some(3);
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let min_inertia = (0..8)
+            .map(|_| {
+                let mut params = params.clone();
+                params.seed = Some(rng.gen::<u64>());
+                inertia(&cluster(&histogram, &params))
+            })
+            .fold(f32::INFINITY, f32::min);
+
+        params.n_init = 8;
+        let best = cluster(&histogram, &params);
+
+        assert_eq!(inertia(&best), min_inertia);
+        assert_eq!(inertia(&cluster(&histogram, &params)), min_inertia);
+    }
 }
@@ -0,0 +1,459 @@
+use crate::img_utils::{color_metric::ColorMetric, color_point::ColorPoint, histogram::Histogram};
+use crate::kmeans::{
+    cluster_params::ClusterParams,
+    quantizer::{Quantization, Quantizer},
+};
+use rand::{prelude::*, rngs::StdRng};
+
+/// K-medoids: every base color is a color of the image the histogram was built from.
+/// Small histograms are clustered by PAM, larger ones by CLARA (PAM on samples).
+pub struct KMedoids;
+
+impl Quantizer for KMedoids {
+    fn quantize(&self, histogram: &Histogram, params: &ClusterParams) -> Quantization {
+        cluster(histogram, params).into()
+    }
+}
+
+/// Histograms up to this size are clustered by PAM directly.
+const PAM_MAX_SIZE: usize = 512;
+const CLARA_NUMBER_OF_SAMPLES: usize = 5;
+const CLARA_MIN_SAMPLE_SIZE: usize = 256;
+/// PAM allocates a sample size squared distance matrix, whatever the number of clusters.
+const CLARA_MAX_SAMPLE_SIZE: usize = 1024;
+
+struct Entry {
+    histogram_point: ColorPoint,
+    coordinates: Vec<f32>,
+}
+
+/// Calculate base colors by k-medoids.
+pub fn cluster(histogram: &Histogram, params: &ClusterParams) -> Vec<ColorPoint> {
+    let number_of_clusters = params.number_of_clusters as usize;
+    let color_space = params.clustering_color_space();
+    let metric = params.metric.metric();
+
+    let entries = histogram
+        .existing_colors()
+        .into_iter()
+        .map(|histogram_point| Entry {
+            coordinates: color_space.coordinates(&histogram_point.color),
+            histogram_point,
+        })
+        .collect::<Vec<_>>();
+
+    if entries.is_empty() || number_of_clusters == 0 {
+        return Vec::new();
+    }
+
+    let medoids = match entries.len() <= PAM_MAX_SIZE {
+        true => {
+            let all = (0..entries.len()).collect::<Vec<_>>();
+            pam(
+                &entries,
+                &all,
+                number_of_clusters,
+                params.max_try_count,
+                metric,
+            )
+        }
+        false => clara(&entries, number_of_clusters, params, metric),
+    };
+
+    let mut weights = vec![0_f32; medoids.len()];
+    entries.iter().for_each(|entry| {
+        let (nearest, _) = nearest_medoid(&entry.coordinates, &entries, &medoids, metric);
+        weights[nearest] += params.weight_mode.point_weight(&entry.histogram_point);
+    });
+
+    let total_weight = weights.iter().sum::<f32>();
+
+    medoids
+        .iter()
+        .zip(weights)
+        .map(|(medoid, weight)| {
+            ColorPoint::new(
+                entries[*medoid].histogram_point.color.clone(),
+                weight / total_weight,
+            )
+        })
+        .collect()
+}
+
+/// Run PAM on random samples of distinct colors and keep the medoids
+/// with the lowest cost over the whole histogram.
+fn clara(
+    entries: &[Entry],
+    number_of_clusters: usize,
+    params: &ClusterParams,
+    metric: &dyn ColorMetric,
+) -> Vec<usize> {
+    let mut rng = match params.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let sample_size = (40 + 2 * number_of_clusters)
+        .clamp(CLARA_MIN_SAMPLE_SIZE, CLARA_MAX_SAMPLE_SIZE)
+        .min(entries.len());
+
+    let mut best: Option<(Vec<usize>, f32)> = None;
+
+    (0..CLARA_NUMBER_OF_SAMPLES).for_each(|_| {
+        let mut sample = rand::seq::index::sample(&mut rng, entries.len(), sample_size).into_vec();
+        sample.sort_unstable();
+
+        let medoids = pam(
+            entries,
+            &sample,
+            number_of_clusters,
+            params.max_try_count,
+            metric,
+        );
+        let cost = entries
+            .iter()
+            .map(|entry| {
+                let (_, distance) = nearest_medoid(&entry.coordinates, entries, &medoids, metric);
+                entry.histogram_point.weight * distance
+            })
+            .sum::<f32>();
+
+        if best
+            .as_ref()
+            .map_or(true, |(_, best_cost)| cost < *best_cost)
+        {
+            best = Some((medoids, cost));
+        }
+    });
+
+    best.map(|(medoids, _)| medoids).unwrap_or_default()
+}
+
+/// Partitioning around medoids over the given entries, weighted by pixel count.
+/// Returns indexes of the medoids in `entries`.
+fn pam(
+    entries: &[Entry],
+    candidates: &[usize],
+    number_of_clusters: usize,
+    max_swap_count: u32,
+    metric: &dyn ColorMetric,
+) -> Vec<usize> {
+    let n = candidates.len();
+
+    if number_of_clusters >= n {
+        return candidates.to_vec();
+    }
+
+    let weights = candidates
+        .iter()
+        .map(|ix| entries[*ix].histogram_point.weight)
+        .collect::<Vec<_>>();
+    let mut distances = vec![0_f32; n * n];
+    (0..n).for_each(|i| {
+        (i + 1..n).for_each(|j| {
+            let distance = metric.distance(
+                &entries[candidates[i]].coordinates,
+                &entries[candidates[j]].coordinates,
+            );
+            distances[i * n + j] = distance;
+            distances[j * n + i] = distance;
+        });
+    });
+    let distance = |i: usize, j: usize| distances[i * n + j];
+
+    // BUILD: greedily add the medoid that lowers the cost the most.
+    let mut medoids: Vec<usize> = Vec::with_capacity(number_of_clusters);
+    let mut nearest = vec![f32::INFINITY; n];
+
+    while medoids.len() < number_of_clusters {
+        // the first medoid has the lowest total distance.
+        let gain = |candidate: usize| match medoids.is_empty() {
+            true => -(0..n)
+                .map(|j| weights[j] * distance(candidate, j))
+                .sum::<f32>(),
+            false => (0..n)
+                .map(|j| weights[j] * (nearest[j] - distance(candidate, j)).max(0_f32))
+                .sum::<f32>(),
+        };
+
+        let best = (0..n)
+            .filter(|candidate| !medoids.contains(candidate))
+            .map(|candidate| (candidate, gain(candidate)))
+            .fold(None, |best: Option<(usize, f32)>, current| match best {
+                Some(best) if best.1 >= current.1 => Some(best),
+                _ => Some(current),
+            });
+
+        let (medoid, _) = match best {
+            Some(value) => value,
+            None => break,
+        };
+
+        medoids.push(medoid);
+        (0..n).for_each(|j| nearest[j] = nearest[j].min(distance(medoid, j)));
+    }
+
+    // SWAP: replace a medoid by a non medoid while the cost goes down.
+    // FastPAM1 delta: one pass over the points scores the candidate against every medoid.
+    for _ in 0..max_swap_count {
+        let (nearest_slots, nearest, second_nearest) = nearest_two(&medoids, n, &distance);
+
+        let mut best_swap: Option<(usize, usize, f32)> = None;
+
+        (0..n)
+            .filter(|candidate| !medoids.contains(candidate))
+            .for_each(|candidate| {
+                let mut deltas = vec![0_f32; medoids.len()];
+                let mut shared_delta = 0_f32;
+
+                (0..n).for_each(|j| {
+                    let candidate_distance = distance(candidate, j);
+                    // the point moves to the candidate whichever medoid is removed.
+                    let gain = (candidate_distance - nearest[j]).min(0_f32);
+                    shared_delta += weights[j] * gain;
+                    // removing its medoid sends it to the candidate or to the second nearest one.
+                    deltas[nearest_slots[j]] += weights[j]
+                        * (candidate_distance.min(second_nearest[j]) - nearest[j] - gain);
+                });
+
+                deltas.iter().enumerate().for_each(|(slot, delta)| {
+                    let delta = delta + shared_delta;
+                    if best_swap.map_or(true, |(_, _, best_delta)| delta < best_delta) {
+                        best_swap = Some((slot, candidate, delta));
+                    }
+                });
+            });
+
+        match best_swap {
+            Some((slot, candidate, delta)) if delta < 0_f32 => medoids[slot] = candidate,
+            _ => break,
+        }
+    }
+
+    medoids.iter().map(|medoid| candidates[*medoid]).collect()
+}
+
+/// For every point: slot of the nearest medoid, distance to it and to the second nearest one.
+fn nearest_two(
+    medoids: &[usize],
+    n: usize,
+    distance: &impl Fn(usize, usize) -> f32,
+) -> (Vec<usize>, Vec<f32>, Vec<f32>) {
+    let mut nearest_slots = vec![0_usize; n];
+    let mut nearest = vec![f32::INFINITY; n];
+    let mut second_nearest = vec![f32::INFINITY; n];
+
+    (0..n).for_each(|j| {
+        medoids.iter().enumerate().for_each(|(slot, medoid)| {
+            let value = distance(*medoid, j);
+
+            if value < nearest[j] {
+                second_nearest[j] = nearest[j];
+                nearest[j] = value;
+                nearest_slots[j] = slot;
+            } else if value < second_nearest[j] {
+                second_nearest[j] = value;
+            }
+        });
+    });
+
+    (nearest_slots, nearest, second_nearest)
+}
+
+/// Position in `medoids` of the nearest medoid and the distance to it.
+fn nearest_medoid(
+    coordinates: &[f32],
+    entries: &[Entry],
+    medoids: &[usize],
+    metric: &dyn ColorMetric,
+) -> (usize, f32) {
+    medoids
+        .iter()
+        .enumerate()
+        .map(|(slot, medoid)| {
+            (
+                slot,
+                metric.distance(coordinates, &entries[*medoid].coordinates),
+            )
+        })
+        .fold((0, f32::INFINITY), |best, current| {
+            match current.1 < best.1 {
+                true => current,
+                false => best,
+            }
+        })
+}
+
+/// Replace every base color by the nearest image color of the histogram.
+/// Base colors snapped to the same color are merged.
+pub fn snap_to_existing(
+    centers: Vec<ColorPoint>,
+    histogram: &Histogram,
+    params: &ClusterParams,
+) -> Vec<ColorPoint> {
+    let color_space = params.clustering_color_space();
+    let metric = params.metric.metric();
+
+    let entries = histogram
+        .existing_colors()
+        .into_iter()
+        .map(|histogram_point| Entry {
+            coordinates: color_space.coordinates(&histogram_point.color),
+            histogram_point,
+        })
+        .collect::<Vec<_>>();
+
+    if entries.is_empty() {
+        return centers;
+    }
+
+    let all = (0..entries.len()).collect::<Vec<_>>();
+    let mut snapped: Vec<ColorPoint> = Vec::new();
+
+    centers.into_iter().for_each(|center| {
+        let coordinates = color_space.coordinates(&center.color);
+        let (nearest, _) = nearest_medoid(&coordinates, &entries, &all, metric);
+        let color = &entries[nearest].histogram_point.color;
+
+        match snapped.iter_mut().find(|point| point.color == *color) {
+            Some(point) => {
+                point.weight += center.weight;
+                point.mixture_weight = match (point.mixture_weight, center.mixture_weight) {
+                    (Some(a), Some(b)) => Some(a + b),
+                    (a, b) => a.or(b),
+                };
+            }
+            None => snapped.push(ColorPoint {
+                color: color.clone(),
+                ..center
+            }),
+        }
+    });
+
+    snapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_utils::rgb_color::RgbColor;
+    use crate::kmeans::cluster_params::WeightMode;
+
+    fn test_histogram(step: u32) -> Histogram {
+        let mut histogram = Histogram::new();
+        (0..step).for_each(|i| {
+            (0..step).for_each(|j| {
+                histogram.push_color(&RgbColor::new(200 + i, 10 + j, 10));
+                histogram.push_color(&RgbColor::new(10, 10 + j, 200 + i));
+            });
+        });
+
+        histogram
+    }
+
+    #[test]
+    fn cluster_returns_histogram_colors() {
+        // PAM for the small histogram, CLARA for the large one.
+        [5, 30].into_iter().for_each(|step| {
+            let histogram = test_histogram(step);
+            let mut params = ClusterParams::new(2, 30);
+            params.seed = Some(2);
+            params.weight_mode = WeightMode::Pixels;
+
+            let centers = cluster(&histogram, &params);
+            let colors = histogram
+                .to_vec()
+                .into_iter()
+                .map(|point| point.color)
+                .collect::<Vec<_>>();
+
+            assert_eq!(centers.len(), 2);
+            centers.iter().for_each(|point| {
+                assert!(colors.contains(&point.color));
+                assert_eq!(point.weight, 0.5);
+            });
+            assert!(centers.iter().any(|point| point.color.r >= 200));
+            assert!(centers.iter().any(|point| point.color.b >= 200));
+        });
+    }
+
+    #[test]
+    fn clara_sample_size_is_capped() {
+        let histogram = test_histogram(24);
+        let mut params = ClusterParams::new(100_000, 30);
+        params.seed = Some(2);
+
+        let centers = cluster(&histogram, &params);
+
+        assert_eq!(centers.len(), CLARA_MAX_SAMPLE_SIZE);
+    }
+
+    #[test]
+    fn pam_finds_group_medians() {
+        let histogram = test_histogram(5);
+        let mut params = ClusterParams::new(2, 30);
+        params.weight_mode = WeightMode::Pixels;
+
+        let mut colors = cluster(&histogram, &params)
+            .into_iter()
+            .map(|point| point.color.to_vec())
+            .collect::<Vec<_>>();
+        colors.sort();
+
+        assert_eq!(colors, vec![vec![10, 12, 202], vec![202, 12, 10]]);
+    }
+
+    #[test]
+    fn pam_swaps_single_medoid() {
+        let mut histogram = Histogram::new();
+        (0..5_u32).for_each(|i| {
+            (0..=i).for_each(|_| histogram.push_color(&RgbColor::new(10 * i, 0, 0)));
+        });
+
+        // BUILD picks the lowest total distance, the swap keeps it.
+        let centers = cluster(&histogram, &ClusterParams::new(1, 30));
+
+        assert_eq!(centers.len(), 1);
+        assert_eq!(centers[0].color.to_vec(), vec![30, 0, 0]);
+    }
+
+    #[test]
+    fn snap_to_existing_merges_centers() {
+        let histogram = test_histogram(3);
+        let params = ClusterParams::new(3, 30);
+
+        let centers = vec![
+            ColorPoint::new(RgbColor::new(250, 0, 0), 0.25),
+            ColorPoint::new(RgbColor::new(240, 0, 0), 0.25),
+            ColorPoint::new(RgbColor::new(0, 0, 100), 0.5),
+        ];
+
+        let snapped = snap_to_existing(centers, &histogram, &params);
+
+        assert_eq!(snapped.len(), 2);
+        assert_eq!(snapped[0].color.to_vec(), vec![202, 10, 10]);
+        assert_eq!(snapped[0].weight, 0.5);
+        assert_eq!(snapped[1].color.to_vec(), vec![10, 10, 200]);
+    }
+
+    #[test]
+    fn coarse_bins_give_image_colors() {
+        // bin means are no image colors: (1, 1, 1) and (241, 1, 1).
+        let mut histogram = Histogram::with_existing_colors(4);
+        [0, 0, 3].into_iter().for_each(|value| {
+            histogram.push_color(&RgbColor::new(value, value, value));
+            histogram.push_color(&RgbColor::new(240 + value, value, value));
+        });
+        let params = ClusterParams::new(2, 30);
+
+        let mut colors = cluster(&histogram, &params)
+            .into_iter()
+            .map(|point| point.color.to_vec())
+            .collect::<Vec<_>>();
+        colors.sort();
+        assert_eq!(colors, vec![vec![0, 0, 0], vec![240, 0, 0]]);
+
+        let centers = vec![ColorPoint::new(RgbColor::new(2, 2, 2), 1.0)];
+        let snapped = snap_to_existing(centers, &histogram, &params);
+        assert_eq!(snapped[0].color.to_vec(), vec![0, 0, 0]);
+    }
+}
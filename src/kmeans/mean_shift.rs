@@ -0,0 +1,339 @@
+use crate::img_utils::{
+    color_metric::ColorMetric, color_point::ColorPoint, color_space::ColorSpace,
+    histogram::Histogram,
+};
+use crate::kmeans::{
+    cluster_params::{ClusterParams, NUMBER_OF_CLUSTERS_LIMIT},
+    quantizer::{Quantization, Quantizer},
+};
+use std::collections::HashMap;
+
+/// Mean-shift with a flat kernel of `params.bandwidth` radius in the clustering color space,
+/// distances are measured by `params.metric`.
+/// Finds the number of clusters by itself, `number_of_clusters` is ignored.
+pub struct MeanShift;
+
+impl Quantizer for MeanShift {
+    fn quantize(&self, histogram: &Histogram, params: &ClusterParams) -> Quantization {
+        cluster(histogram, params).into()
+    }
+}
+
+const DIM: usize = 3;
+/// A seed stops when it moves less than this part of the bandwidth.
+const SHIFT_TOLERANCE: f32 = 1e-3;
+
+type Cell = [i64; DIM];
+
+struct Point {
+    coordinates: [f32; DIM],
+    pixel_count: f32,
+}
+
+/// Positions bucketed into cubes with the side of the bandwidth,
+/// neighbors of a position are in the 27 surrounding cubes.
+struct Grid {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<usize>>,
+}
+
+impl Grid {
+    fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn with_points(points: &[Point], cell_size: f32) -> Self {
+        let mut grid = Self::new(cell_size);
+        points
+            .iter()
+            .enumerate()
+            .for_each(|(ix, point)| grid.insert(ix, &point.coordinates));
+
+        grid
+    }
+
+    fn insert(&mut self, ix: usize, position: &[f32; DIM]) {
+        self.cells
+            .entry(cell(position, self.cell_size))
+            .or_default()
+            .push(ix);
+    }
+
+    fn neighbors(&self, position: &[f32; DIM]) -> impl Iterator<Item = usize> + '_ {
+        let [x, y, z] = cell(position, self.cell_size);
+
+        (-1..=1_i64)
+            .flat_map(move |dx| {
+                (-1..=1_i64).flat_map(move |dy| (-1..=1_i64).map(move |dz| [dx, dy, dz]))
+            })
+            .filter_map(move |[dx, dy, dz]| self.cells.get(&[x + dx, y + dy, z + dz]))
+            .flatten()
+            .copied()
+    }
+}
+
+fn cell(coordinates: &[f32; DIM], cell_size: f32) -> Cell {
+    coordinates.map(|value| (value / cell_size).floor() as i64)
+}
+
+/// Default bandwidth for the color space, about a tenth of the lightness range.
+pub fn default_bandwidth(color_space: ColorSpace) -> f32 {
+    match color_space {
+        ColorSpace::Srgb | ColorSpace::LinearRgb => 32_f32,
+        ColorSpace::Lab => 12_f32,
+        ColorSpace::Oklab => 0.12,
+    }
+}
+
+/// Smallest bandwidth for the color space. A narrower kernel gives nearly every
+/// distinct color its own mode, which is slow and no palette.
+pub fn min_bandwidth(color_space: ColorSpace) -> f32 {
+    default_bandwidth(color_space) / 4_f32
+}
+
+/// Calculate base colors as the modes of the pixel density.
+/// At most `NUMBER_OF_CLUSTERS_LIMIT` densest modes are returned.
+pub fn cluster(histogram: &Histogram, params: &ClusterParams) -> Vec<ColorPoint> {
+    let color_space = params.clustering_color_space();
+    let metric = params.metric.metric();
+    let bandwidth = params
+        .bandwidth
+        .filter(|bandwidth| *bandwidth > 0_f32)
+        .unwrap_or(default_bandwidth(color_space));
+
+    let histogram_points = histogram.to_vec();
+    let points = histogram_points
+        .iter()
+        .map(|point| {
+            let coordinates = color_space.coordinates(&point.color);
+            Point {
+                coordinates: std::array::from_fn(|i| coordinates[i]),
+                pixel_count: point.weight,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if points.is_empty() {
+        return Vec::new();
+    }
+
+    let grid = Grid::with_points(&points, bandwidth);
+
+    // one seed per occupied cell, placed at the weighted mean of the cell.
+    let mut cells = grid.cells.iter().collect::<Vec<_>>();
+    cells.sort_by_key(|(cell, _)| **cell);
+
+    let mut modes = cells
+        .iter()
+        .map(|(_, point_ixs)| {
+            let seed = weighted_mean(point_ixs.iter().copied(), &points).0;
+            shift(
+                seed,
+                &points,
+                &grid,
+                bandwidth,
+                metric,
+                params.max_try_count,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // denser modes absorb the modes within the bandwidth.
+    modes.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut unique_modes: Vec<[f32; DIM]> = Vec::new();
+    let mut mode_grid = Grid::new(bandwidth);
+    for (mode, _) in &modes {
+        if unique_modes.len() >= NUMBER_OF_CLUSTERS_LIMIT as usize {
+            break;
+        }
+
+        if mode_grid
+            .neighbors(mode)
+            .all(|ix| distance(metric, &unique_modes[ix], mode) > bandwidth)
+        {
+            mode_grid.insert(unique_modes.len(), mode);
+            unique_modes.push(*mode);
+        }
+    }
+
+    let mut weights = vec![0_f32; unique_modes.len()];
+    histogram_points
+        .iter()
+        .zip(&points)
+        .for_each(|(histogram_point, point)| {
+            let nearest_mode = |ixs: &mut dyn Iterator<Item = usize>| {
+                ixs.map(|ix| (ix, distance(metric, &unique_modes[ix], &point.coordinates)))
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(ix, _)| ix)
+            };
+            // points farther than a cell from every mode fall back to all the modes.
+            let nearest = nearest_mode(&mut mode_grid.neighbors(&point.coordinates))
+                .or_else(|| nearest_mode(&mut (0..unique_modes.len())))
+                .unwrap_or(0);
+            weights[nearest] += params.weight_mode.point_weight(histogram_point);
+        });
+
+    let total_weight = weights.iter().sum::<f32>();
+    let mut centers: Vec<ColorPoint> = Vec::new();
+
+    unique_modes
+        .iter()
+        .zip(&weights)
+        .filter(|(_, weight)| **weight > 0_f32)
+        .for_each(|(mode, weight)| match color_space.to_rgb(mode) {
+            Ok(color) => centers.push(ColorPoint::new(color, weight / total_weight)),
+            Err(err) => println!("Unable to get Rgb color for mean-shift mode. Err: {err}"),
+        });
+
+    centers.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+
+    centers
+}
+
+/// Move the seed to the mean of its neighborhood until it settles.
+/// Returns the mode and the pixel count around it.
+fn shift(
+    seed: [f32; DIM],
+    points: &[Point],
+    grid: &Grid,
+    bandwidth: f32,
+    metric: &dyn ColorMetric,
+    max_try_count: u32,
+) -> ([f32; DIM], f32) {
+    let mut position = seed;
+    let mut pixel_count = 0_f32;
+
+    for _ in 0..max_try_count.max(1) {
+        let window = grid
+            .neighbors(&position)
+            .filter(|&ix| distance(metric, &points[ix].coordinates, &position) <= bandwidth);
+        let (mean, window_pixel_count) = weighted_mean(window, points);
+
+        if window_pixel_count <= 0_f32 {
+            break;
+        }
+
+        let moved = distance(metric, &mean, &position);
+        position = mean;
+        pixel_count = window_pixel_count;
+
+        if moved <= SHIFT_TOLERANCE * bandwidth {
+            break;
+        }
+    }
+
+    (position, pixel_count)
+}
+
+fn weighted_mean(point_ixs: impl Iterator<Item = usize>, points: &[Point]) -> ([f32; DIM], f32) {
+    let mut sums = [0_f32; DIM];
+    let mut pixel_count = 0_f32;
+
+    point_ixs.for_each(|ix| {
+        let point = &points[ix];
+        pixel_count += point.pixel_count;
+        sums.iter_mut()
+            .zip(point.coordinates)
+            .for_each(|(sum, value)| *sum += value * point.pixel_count);
+    });
+
+    match pixel_count > 0_f32 {
+        true => (sums.map(|sum| sum / pixel_count), pixel_count),
+        false => (sums, 0_f32),
+    }
+}
+
+/// Plain distance of the metric, also for metrics reporting squared distances.
+/// Candidates come from the neighboring grid cells, so the kernel of metrics
+/// shorter than the Euclidean distance (CIE94, CIEDE2000) is cut to those cells.
+fn distance(metric: &dyn ColorMetric, a: &[f32; DIM], b: &[f32; DIM]) -> f32 {
+    metric.squared_distance(a, b).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::img_utils::{color_metric::DistanceMetric, rgb_color::RgbColor};
+    use crate::kmeans::cluster_params::WeightMode;
+
+    #[test]
+    fn cluster_finds_modes_without_number_of_clusters() {
+        let mut histogram = Histogram::new();
+        [(230, 20, 20), (20, 230, 20), (20, 20, 230)]
+            .iter()
+            .enumerate()
+            .for_each(|(group, (r, g, b))| {
+                (0..10_u32).for_each(|i| {
+                    (0..=group).for_each(|_| {
+                        histogram.push_color(&RgbColor::new(r + i % 5, g + i / 5, *b));
+                    });
+                });
+            });
+
+        // number of clusters is ignored.
+        let mut params = ClusterParams::new(8, 30);
+        params.weight_mode = WeightMode::Pixels;
+        params.bandwidth = Some(20.0);
+
+        let centers = cluster(&histogram, &params);
+
+        assert_eq!(centers.len(), 3);
+        assert!(centers[0].color.b > 200);
+        assert!(centers[1].color.g > 200);
+        assert!(centers[2].color.r > 200);
+        assert!((centers[0].weight - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn wide_bandwidth_merges_modes() {
+        let mut histogram = Histogram::new();
+        histogram.push_color(&RgbColor::new(100, 100, 100));
+        histogram.push_color(&RgbColor::new(110, 100, 100));
+
+        let mut params = ClusterParams::new(4, 30);
+        params.bandwidth = Some(50.0);
+        assert_eq!(cluster(&histogram, &params).len(), 1);
+
+        params.bandwidth = Some(5.0);
+        assert_eq!(cluster(&histogram, &params).len(), 2);
+    }
+
+    #[test]
+    fn small_bandwidth_returns_bounded_modes() {
+        let mut histogram = Histogram::new();
+        (0..20_u32).for_each(|r| {
+            (0..20_u32).for_each(|g| {
+                (0..20_u32).for_each(|b| {
+                    histogram.push_color(&RgbColor::new(r * 12, g * 12, b * 12));
+                });
+            });
+        });
+
+        let mut params = ClusterParams::new(4, 30);
+        params.bandwidth = Some(1.0);
+
+        let centers = cluster(&histogram, &params);
+
+        assert_eq!(centers.len(), NUMBER_OF_CLUSTERS_LIMIT as usize);
+        assert!((centers.iter().map(|center| center.weight).sum::<f32>() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn kernel_uses_metric() {
+        let mut histogram = Histogram::new();
+        histogram.push_color(&RgbColor::new(100, 100, 100));
+        histogram.push_color(&RgbColor::new(110, 110, 100));
+
+        // Euclidean distance is about 14, Manhattan distance is 20.
+        let mut params = ClusterParams::new(4, 30);
+        params.bandwidth = Some(15.0);
+        assert_eq!(cluster(&histogram, &params).len(), 1);
+
+        params.metric = DistanceMetric::Manhattan;
+        assert_eq!(cluster(&histogram, &params).len(), 2);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::img_utils::{color_point::ColorPoint, histogram::Histogram, rgb_color::RgbColor};
+use crate::kmeans::{
+    cluster_params::ClusterParams,
+    quantizer::{Quantization, Quantizer},
+};
+
+/// Deterministic single pass median cut over the histogram.
+pub struct MedianCut;
+
+impl Quantizer for MedianCut {
+    fn quantize(&self, histogram: &Histogram, params: &ClusterParams) -> Quantization {
+        cluster(histogram, params).into()
+    }
+}
+
+struct BoxEntry {
+    histogram_point: ColorPoint,
+    coordinates: Vec<f32>,
+}
+
+/// Calculate base colors by median cut.
+pub fn cluster(histogram: &Histogram, params: &ClusterParams) -> Vec<ColorPoint> {
+    let number_of_clusters = params.number_of_clusters as usize;
+    let color_space = params.clustering_color_space();
+
+    let entries = histogram
+        .to_vec()
+        .into_iter()
+        .map(|histogram_point| BoxEntry {
+            coordinates: color_space.coordinates(&histogram_point.color),
+            histogram_point,
+        })
+        .collect::<Vec<_>>();
+
+    if entries.is_empty() || number_of_clusters == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![entries];
+
+    while boxes.len() < number_of_clusters {
+        // pick up the box with the widest channel range.
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, entries)| entries.len() > 1)
+            .map(|(i, entries)| (i, widest_axis(entries)))
+            .max_by(|(_, (_, a)), (_, (_, b))| a.total_cmp(b));
+
+        let (box_ix, (axis, range)) = match widest {
+            Some(value) => value,
+            None => break,
+        };
+
+        if range <= 0_f32 {
+            break;
+        }
+
+        let mut entries = boxes.swap_remove(box_ix);
+        let upper = split_at_median(&mut entries, axis);
+        boxes.push(entries);
+        boxes.push(upper);
+    }
+
+    let total_weight = boxes
+        .iter()
+        .flatten()
+        .map(|entry| params.weight_mode.point_weight(&entry.histogram_point))
+        .sum::<f32>();
+
+    let mut centers: Vec<ColorPoint> = Vec::new();
+
+    boxes.iter().for_each(|entries| {
+        let box_weight = entries
+            .iter()
+            .map(|entry| params.weight_mode.point_weight(&entry.histogram_point))
+            .sum::<f32>();
+
+        match color_space.to_rgb(&mean(entries)) {
+            Ok(color) => centers.push(ColorPoint::new(color, box_weight / total_weight)),
+            Err(err) => println!("Unable to get Rgb color for median cut box. Err: {err}"),
+        }
+    });
+
+    centers
+}
+
+/// Axis with the largest range of values and the range itself.
+fn widest_axis(entries: &[BoxEntry]) -> (usize, f32) {
+    (0..RgbColor::dim())
+        .map(|axis| {
+            let (min, max) =
+                entries
+                    .iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), entry| {
+                        let value = entry.coordinates[axis];
+                        (min.min(value), max.max(value))
+                    });
+
+            (axis, max - min)
+        })
+        .fold((0, f32::NEG_INFINITY), |best, current| {
+            match current.1 > best.1 {
+                true => current,
+                false => best,
+            }
+        })
+}
+
+/// Sort entries along the axis and split them at the pixel weighted median.
+/// Returns the upper part, the lower part stays in `entries`.
+fn split_at_median(entries: &mut Vec<BoxEntry>, axis: usize) -> Vec<BoxEntry> {
+    entries.sort_by(|a, b| a.coordinates[axis].total_cmp(&b.coordinates[axis]));
+
+    let total_weight = entries
+        .iter()
+        .map(|entry| entry.histogram_point.weight)
+        .sum::<f32>();
+
+    let mut accumulated = 0_f32;
+    let median_ix = entries
+        .iter()
+        .position(|entry| {
+            accumulated += entry.histogram_point.weight;
+            accumulated >= total_weight / 2_f32
+        })
+        .unwrap_or(0);
+
+    // both parts must keep at least one entry.
+    let split_ix = (median_ix + 1).clamp(1, entries.len() - 1);
+
+    entries.split_off(split_ix)
+}
+
+/// Pixel weighted mean of box coordinates.
+fn mean(entries: &[BoxEntry]) -> Vec<f32> {
+    let mut sum = vec![0_f32; RgbColor::dim()];
+    let mut total_weight = 0_f32;
+
+    entries.iter().for_each(|entry| {
+        let weight = entry.histogram_point.weight;
+        total_weight += weight;
+        sum.iter_mut()
+            .zip(&entry.coordinates)
+            .for_each(|(sum, value)| *sum += value * weight);
+    });
+
+    sum.iter().map(|value| value / total_weight).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cluster_splits_distinct_groups() {
+        let mut histogram = Histogram::new();
+        (0..10_u32).for_each(|i| {
+            histogram.push_color(&RgbColor::new(i, i, 0));
+            histogram.push_color(&RgbColor::new(250 - i, i, 0));
+            histogram.push_color(&RgbColor::new(i, 250 - i, 0));
+            histogram.push_color(&RgbColor::new(250 - i, 250 - i, 0));
+        });
+
+        let params = ClusterParams::new(4, 0);
+        let centers = cluster(&histogram, &params);
+
+        assert_eq!(centers.len(), 4);
+        [(4, 4), (245, 4), (4, 245), (245, 245)]
+            .iter()
+            .for_each(|(r, g)| {
+                assert!(centers.iter().any(
+                    |point| point.color.r.abs_diff(*r) <= 1 && point.color.g.abs_diff(*g) <= 1
+                ));
+            });
+        centers
+            .iter()
+            .for_each(|point| assert!((point.weight - 0.25).abs() < 1e-6));
+    }
+
+    #[test]
+    fn cluster_stops_when_colors_run_out() {
+        let mut histogram = Histogram::new();
+        histogram.push_color(&RgbColor::new(10, 20, 30));
+        histogram.push_color(&RgbColor::new(200, 20, 30));
+
+        let params = ClusterParams::new(5, 0);
+        assert_eq!(cluster(&histogram, &params).len(), 2);
+    }
+}
@@ -0,0 +1,339 @@
+use crate::img_utils::{color_point::ColorPoint, histogram::Histogram, rgb_color::RgbColor};
+use crate::kmeans::{
+    cluster_params::ClusterParams,
+    quantizer::{Quantization, Quantizer},
+};
+
+/// Octree color quantization.
+/// Works in sRGB and always reports pixel share weights.
+pub struct Octree;
+
+impl Quantizer for Octree {
+    fn quantize(&self, histogram: &Histogram, params: &ClusterParams) -> Quantization {
+        cluster(histogram, params).into()
+    }
+}
+
+const MAX_DEPTH: usize = 8;
+const NUMBER_OF_CHILDREN: usize = 8;
+/// Upper bound of leaves kept while the tree is built, keeps memory bounded for huge histograms.
+const MAX_LEAVES: usize = 4096;
+
+#[derive(Clone)]
+struct Node {
+    children: [Option<usize>; NUMBER_OF_CHILDREN],
+    pixel_count: f64,
+    color_sums: [f64; 3],
+    is_leaf: bool,
+}
+
+impl Node {
+    fn new(is_leaf: bool) -> Self {
+        Self {
+            children: [None; NUMBER_OF_CHILDREN],
+            pixel_count: 0_f64,
+            color_sums: [0_f64; 3],
+            is_leaf,
+        }
+    }
+}
+
+struct ColorOctree {
+    nodes: Vec<Node>,
+    free_nodes: Vec<usize>,
+    /// Internal nodes grouped by tree level.
+    reducible: Vec<Vec<usize>>,
+    leaf_count: usize,
+}
+
+impl ColorOctree {
+    fn new() -> Self {
+        let mut reducible = vec![Vec::new(); MAX_DEPTH];
+        reducible[0].push(0);
+
+        Self {
+            nodes: vec![Node::new(false)],
+            free_nodes: Vec::new(),
+            reducible,
+            leaf_count: 0,
+        }
+    }
+
+    fn insert(&mut self, color: &RgbColor, pixel_count: f64) {
+        let channels = [color.r, color.g, color.b];
+        let mut node_ix = 0_usize;
+        let mut level = 0_usize;
+
+        loop {
+            let node = &mut self.nodes[node_ix];
+            node.pixel_count += pixel_count;
+            node.color_sums
+                .iter_mut()
+                .zip(channels)
+                .for_each(|(sum, value)| *sum += value as f64 * pixel_count);
+
+            if node.is_leaf {
+                break;
+            }
+
+            let child = child_index(&channels, level);
+
+            node_ix = match self.nodes[node_ix].children[child] {
+                Some(child_ix) => child_ix,
+                None => {
+                    let child_ix = self.allocate_node(level + 1);
+                    self.nodes[node_ix].children[child] = Some(child_ix);
+                    child_ix
+                }
+            };
+
+            level += 1;
+        }
+    }
+
+    fn allocate_node(&mut self, level: usize) -> usize {
+        let is_leaf = level == MAX_DEPTH;
+        let node = Node::new(is_leaf);
+
+        let node_ix = match self.free_nodes.pop() {
+            Some(node_ix) => {
+                self.nodes[node_ix] = node;
+                node_ix
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        match is_leaf {
+            true => self.leaf_count += 1,
+            false => self.reducible[level].push(node_ix),
+        }
+
+        node_ix
+    }
+
+    /// Merge the latest added node of the deepest level into a single leaf.
+    /// Returns false when nothing is left to merge.
+    fn reduce(&mut self) -> bool {
+        let level = match self.deepest_reducible_level() {
+            Some(level) => level,
+            None => return false,
+        };
+
+        let position = self.reducible[level].len() - 1;
+        self.fold(level, position);
+
+        true
+    }
+
+    /// Merge the leaves with the least pixels until `number_of_leaves` are left.
+    /// Nodes are folded as long as they don't drop below it, then sibling leaves are merged pairwise.
+    fn reduce_to(&mut self, number_of_leaves: usize) {
+        while self.leaf_count > number_of_leaves {
+            let level = match self.deepest_reducible_level() {
+                Some(level) => level,
+                None => return,
+            };
+
+            let nodes = &self.nodes;
+            let position = self.reducible[level]
+                .iter()
+                .enumerate()
+                .min_by(|(_, &a), (_, &b)| nodes[a].pixel_count.total_cmp(&nodes[b].pixel_count))
+                .map(|(position, _)| position)
+                .unwrap();
+
+            let node_ix = self.reducible[level][position];
+            let child_count = self.nodes[node_ix].children.iter().flatten().count();
+
+            match self.leaf_count + 1 - child_count >= number_of_leaves {
+                true => self.fold(level, position),
+                false => self.merge_smallest_children(node_ix),
+            }
+        }
+    }
+
+    fn deepest_reducible_level(&self) -> Option<usize> {
+        self.reducible.iter().rposition(|nodes| !nodes.is_empty())
+    }
+
+    /// Turn the node into a leaf holding all its children.
+    fn fold(&mut self, level: usize, position: usize) {
+        let node_ix = self.reducible[level].swap_remove(position);
+
+        // children of the deepest internal node are always leaves.
+        let children = self.nodes[node_ix].children;
+        children.iter().flatten().for_each(|&child_ix| {
+            self.free_nodes.push(child_ix);
+            self.leaf_count -= 1;
+        });
+
+        let node = &mut self.nodes[node_ix];
+        node.children = [None; NUMBER_OF_CHILDREN];
+        node.is_leaf = true;
+        self.leaf_count += 1;
+    }
+
+    /// Merge the two leaf children of the node with the least pixels into one.
+    fn merge_smallest_children(&mut self, node_ix: usize) {
+        let nodes = &self.nodes;
+        let mut children = nodes[node_ix]
+            .children
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, child)| child.map(|child_ix| (slot, child_ix)))
+            .collect::<Vec<_>>();
+        children.sort_by(|(_, a), (_, b)| nodes[*a].pixel_count.total_cmp(&nodes[*b].pixel_count));
+
+        let (kept_ix, (merged_slot, merged_ix)) = match children[..] {
+            [(_, kept_ix), merged, ..] => (kept_ix, merged),
+            _ => return,
+        };
+
+        let merged = self.nodes[merged_ix].clone();
+        let kept = &mut self.nodes[kept_ix];
+        kept.pixel_count += merged.pixel_count;
+        kept.color_sums
+            .iter_mut()
+            .zip(merged.color_sums)
+            .for_each(|(sum, value)| *sum += value);
+
+        self.nodes[node_ix].children[merged_slot] = None;
+        self.free_nodes.push(merged_ix);
+        self.leaf_count -= 1;
+    }
+
+    fn leaves(&self) -> Vec<&Node> {
+        let mut leaves = Vec::with_capacity(self.leaf_count);
+        let mut stack = vec![0_usize];
+
+        while let Some(node_ix) = stack.pop() {
+            let node = &self.nodes[node_ix];
+
+            match node.is_leaf {
+                true => leaves.push(node),
+                false => stack.extend(node.children.iter().rev().flatten()),
+            }
+        }
+
+        leaves
+    }
+}
+
+fn child_index(channels: &[u32; 3], level: usize) -> usize {
+    let shift = MAX_DEPTH - 1 - level;
+
+    channels.iter().fold(0_usize, |index, value| {
+        (index << 1) | ((*value as usize >> shift) & 1)
+    })
+}
+
+/// Calculate base colors by octree reduction.
+pub fn cluster(histogram: &Histogram, params: &ClusterParams) -> Vec<ColorPoint> {
+    let number_of_clusters = params.number_of_clusters as usize;
+    let vec = histogram.to_vec();
+
+    if vec.is_empty() || number_of_clusters == 0 {
+        return Vec::new();
+    }
+
+    let mut octree = ColorOctree::new();
+
+    vec.iter().for_each(|point| {
+        octree.insert(&point.color, point.weight as f64);
+
+        while octree.leaf_count > MAX_LEAVES && octree.reduce() {}
+    });
+
+    octree.reduce_to(number_of_clusters);
+
+    let total_pixel_count = octree.nodes[0].pixel_count;
+
+    octree
+        .leaves()
+        .iter()
+        .map(|node| {
+            let [r, g, b] = node
+                .color_sums
+                .map(|sum| (sum / node.pixel_count).round() as u32);
+
+            ColorPoint::new(
+                RgbColor::new(r, g, b),
+                (node.pixel_count / total_pixel_count) as f32,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cluster_reduces_to_number_of_clusters() {
+        let mut histogram = Histogram::new();
+        (0..40_u32).for_each(|i| {
+            (0..3).for_each(|_| histogram.push_color(&RgbColor::new(255 - i, 0, 0)));
+            histogram.push_color(&RgbColor::new(0, 0, 255 - i));
+        });
+
+        let params = ClusterParams::new(2, 0);
+        let mut centers = cluster(&histogram, &params);
+        centers.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+
+        assert_eq!(centers.len(), 2);
+        assert!(centers[0].color.r > 200 && centers[0].color.b == 0);
+        assert!(centers[1].color.b > 200 && centers[1].color.r == 0);
+        assert_eq!(centers[0].weight, 0.75);
+        assert_eq!(centers[1].weight, 0.25);
+    }
+
+    #[test]
+    fn cluster_merges_leaves_when_folding_overshoots() {
+        // two groups of 8 colors, each group under a single node of the last level.
+        let mut histogram = Histogram::new();
+        (0..8_u32).for_each(|i| {
+            let (dr, dg, db) = (i & 1, (i >> 1) & 1, (i >> 2) & 1);
+            (0..=i).for_each(|_| histogram.push_color(&RgbColor::new(200 + dr, 10 + dg, 10 + db)));
+            histogram.push_color(&RgbColor::new(10 + dr, 10 + dg, 200 + db));
+        });
+
+        [1, 2, 3, 4, 7, 9, 15, 16]
+            .into_iter()
+            .for_each(|number_of_clusters| {
+                let params = ClusterParams::new(number_of_clusters, 0);
+                let centers = cluster(&histogram, &params);
+
+                assert_eq!(centers.len(), number_of_clusters as usize);
+                let total_weight = centers.iter().map(|point| point.weight).sum::<f32>();
+                assert!((total_weight - 1.0).abs() < 1e-6);
+            });
+
+        // folding the smaller blue group leaves 9 leaves, then red leaves are merged pairwise.
+        let centers = cluster(&histogram, &ClusterParams::new(4, 0));
+        assert_eq!(
+            centers.iter().filter(|point| point.color.b > 100).count(),
+            1
+        );
+        assert_eq!(
+            centers.iter().filter(|point| point.color.r > 100).count(),
+            3
+        );
+    }
+
+    #[test]
+    fn tree_keeps_leaf_bound() {
+        let mut octree = ColorOctree::new();
+        (0..=255_u32).step_by(3).for_each(|r| {
+            (0..=255_u32).step_by(5).for_each(|g| {
+                octree.insert(&RgbColor::new(r, g, 255 - g), 1_f64);
+                while octree.leaf_count > 64 && octree.reduce() {}
+            });
+        });
+
+        assert!(octree.leaf_count <= 64);
+        assert_eq!(octree.leaves().len(), octree.leaf_count);
+    }
+}
@@ -0,0 +1,24 @@
+use crate::img_utils::{color_point::ColorPoint, histogram::Histogram};
+use crate::kmeans::{cluster_params::ClusterParams, histogram_k_means::KMeansDiagnostics};
+
+/// Base colors found by a quantizer.
+#[derive(Debug, Clone, Default)]
+pub struct Quantization {
+    pub centers: Vec<ColorPoint>,
+    /// Details of the k-means loop, `None` for single pass algorithms.
+    pub diagnostics: Option<KMeansDiagnostics>,
+}
+
+impl From<Vec<ColorPoint>> for Quantization {
+    fn from(centers: Vec<ColorPoint>) -> Self {
+        Self {
+            centers,
+            diagnostics: None,
+        }
+    }
+}
+
+/// Reduces a color histogram to a small set of base colors.
+pub trait Quantizer {
+    fn quantize(&self, histogram: &Histogram, params: &ClusterParams) -> Quantization;
+}
@@ -0,0 +1,339 @@
+use crate::img_utils::{color_point::ColorPoint, histogram::Histogram, rgb_color::RgbColor};
+use crate::kmeans::{
+    cluster_params::{ClusterParams, WeightMode},
+    histogram_k_means,
+    quantizer::{Quantization, Quantizer},
+};
+
+/// Xiaolin Wu's variance minimizing quantizer.
+/// Boxes are cut in sRGB, optionally the result seeds k-means refinement.
+pub struct Wu;
+
+impl Quantizer for Wu {
+    fn quantize(&self, histogram: &Histogram, params: &ClusterParams) -> Quantization {
+        let centers = cluster(histogram, params);
+
+        match params.refine_with_kmeans {
+            true => histogram_k_means::cluster_from_centers(histogram, params, &centers),
+            false => centers.into(),
+        }
+    }
+}
+
+/// Significant bits per channel in the moment table.
+const INDEX_BITS: u32 = 5;
+/// Moment table side, index 0 is kept empty for cumulative sums.
+const SIDE: usize = (1 << INDEX_BITS) + 1;
+
+/// Box of the moment table: lower bounds are exclusive, upper bounds are inclusive.
+#[derive(Debug, Clone, Copy)]
+struct Cube {
+    lower: [usize; 3],
+    upper: [usize; 3],
+}
+
+impl Cube {
+    fn volume(&self) -> usize {
+        (0..3).map(|i| self.upper[i] - self.lower[i]).product()
+    }
+}
+
+/// Cumulative moments of the histogram.
+struct Moments {
+    weight: Vec<f64>,
+    distinct: Vec<f64>,
+    sums: [Vec<f64>; 3],
+    squares: Vec<f64>,
+}
+
+/// Moments of a single box.
+#[derive(Clone, Copy, Default)]
+struct BoxMoments {
+    weight: f64,
+    sums: [f64; 3],
+}
+
+impl BoxMoments {
+    fn squared_sum(&self) -> f64 {
+        self.sums.iter().map(|sum| sum * sum).sum::<f64>()
+    }
+
+    fn minus(&self, other: &BoxMoments) -> BoxMoments {
+        BoxMoments {
+            weight: self.weight - other.weight,
+            sums: [0, 1, 2].map(|i| self.sums[i] - other.sums[i]),
+        }
+    }
+}
+
+fn table_index(r: usize, g: usize, b: usize) -> usize {
+    (r * SIDE + g) * SIDE + b
+}
+
+impl Moments {
+    fn from_histogram(histogram: &Histogram) -> Self {
+        let size = SIDE * SIDE * SIDE;
+        let mut moments = Self {
+            weight: vec![0_f64; size],
+            distinct: vec![0_f64; size],
+            sums: [vec![0_f64; size], vec![0_f64; size], vec![0_f64; size]],
+            squares: vec![0_f64; size],
+        };
+
+        histogram.to_vec().iter().for_each(|point| {
+            let channels = [point.color.r, point.color.g, point.color.b];
+            let [r, g, b] = channels.map(|value| ((value >> (8 - INDEX_BITS)) + 1) as usize);
+            let ix = table_index(r, g, b);
+            let weight = point.weight as f64;
+
+            moments.weight[ix] += weight;
+            moments.distinct[ix] += 1_f64;
+            moments
+                .sums
+                .iter_mut()
+                .zip(channels)
+                .for_each(|(sums, value)| {
+                    sums[ix] += value as f64 * weight;
+                });
+            moments.squares[ix] += channels
+                .iter()
+                .map(|value| (*value as f64).powi(2))
+                .sum::<f64>()
+                * weight;
+        });
+
+        accumulate(&mut moments.weight);
+        accumulate(&mut moments.distinct);
+        moments.sums.iter_mut().for_each(|sums| accumulate(sums));
+        accumulate(&mut moments.squares);
+
+        moments
+    }
+
+    fn box_moments(&self, cube: &Cube) -> BoxMoments {
+        BoxMoments {
+            weight: volume(cube, &self.weight),
+            sums: [0, 1, 2].map(|i| volume(cube, &self.sums[i])),
+        }
+    }
+
+    /// Weighted color variance inside the box.
+    fn variance(&self, cube: &Cube) -> f64 {
+        let moments = self.box_moments(cube);
+
+        match moments.weight > 0_f64 {
+            true => volume(cube, &self.squares) - moments.squared_sum() / moments.weight,
+            false => 0_f64,
+        }
+    }
+
+    /// Best cut position along the axis and the score of the cut.
+    fn maximize(&self, cube: &Cube, axis: usize, whole: &BoxMoments) -> Option<(usize, f64)> {
+        let mut best: Option<(usize, f64)> = None;
+
+        (cube.lower[axis] + 1..cube.upper[axis]).for_each(|position| {
+            let mut half_cube = *cube;
+            half_cube.upper[axis] = position;
+
+            let half = self.box_moments(&half_cube);
+            let other = whole.minus(&half);
+
+            if half.weight <= 0_f64 || other.weight <= 0_f64 {
+                return;
+            }
+
+            let score = half.squared_sum() / half.weight + other.squared_sum() / other.weight;
+
+            if best.map_or(true, |(_, best_score)| score > best_score) {
+                best = Some((position, score));
+            }
+        });
+
+        best
+    }
+
+    /// Split the cube in two along the best axis. Returns the new upper part.
+    fn cut(&self, cube: &mut Cube) -> Option<Cube> {
+        let whole = self.box_moments(cube);
+
+        let (axis, position) = (0..3)
+            .filter_map(|axis| {
+                self.maximize(cube, axis, &whole)
+                    .map(|(position, score)| (axis, position, score))
+            })
+            .fold(
+                None,
+                |best: Option<(usize, usize, f64)>, current| match best {
+                    Some(best) if best.2 >= current.2 => Some(best),
+                    _ => Some(current),
+                },
+            )
+            .map(|(axis, position, _)| (axis, position))?;
+
+        let mut upper = *cube;
+        upper.lower[axis] = position;
+        cube.upper[axis] = position;
+
+        Some(upper)
+    }
+}
+
+/// Turn the table into 3D prefix sums.
+fn accumulate(table: &mut [f64]) {
+    (1..SIDE).for_each(|r| {
+        let mut area = [0_f64; SIDE];
+
+        (1..SIDE).for_each(|g| {
+            let mut line = 0_f64;
+
+            (1..SIDE).for_each(|b| {
+                line += table[table_index(r, g, b)];
+                area[b] += line;
+                table[table_index(r, g, b)] = table[table_index(r - 1, g, b)] + area[b];
+            });
+        });
+    });
+}
+
+/// Sum of the table values inside the cube.
+fn volume(cube: &Cube, table: &[f64]) -> f64 {
+    let [r0, g0, b0] = cube.lower;
+    let [r1, g1, b1] = cube.upper;
+
+    let upper = table[table_index(r1, g1, b1)]
+        - table[table_index(r1, g1, b0)]
+        - table[table_index(r1, g0, b1)]
+        + table[table_index(r1, g0, b0)];
+    let lower = table[table_index(r0, g1, b1)]
+        - table[table_index(r0, g1, b0)]
+        - table[table_index(r0, g0, b1)]
+        + table[table_index(r0, g0, b0)];
+
+    upper - lower
+}
+
+/// Calculate base colors by Wu's quantization.
+pub fn cluster(histogram: &Histogram, params: &ClusterParams) -> Vec<ColorPoint> {
+    let number_of_clusters = params.number_of_clusters as usize;
+
+    if histogram.is_empty() || number_of_clusters == 0 {
+        return Vec::new();
+    }
+
+    let moments = Moments::from_histogram(histogram);
+
+    let mut cubes = vec![Cube {
+        lower: [0; 3],
+        upper: [SIDE - 1; 3],
+    }];
+    let mut variances = vec![0_f64];
+    let mut next = 0_usize;
+
+    while cubes.len() < number_of_clusters {
+        match moments.cut(&mut cubes[next]) {
+            Some(upper) => {
+                cubes.push(upper);
+                variances.push(0_f64);
+                [next, cubes.len() - 1].iter().for_each(|&ix| {
+                    variances[ix] = match cubes[ix].volume() > 1 {
+                        true => moments.variance(&cubes[ix]),
+                        false => 0_f64,
+                    };
+                });
+            }
+            // the cube can't be split.
+            None => variances[next] = 0_f64,
+        }
+
+        let (max_ix, max_variance) =
+            variances
+                .iter()
+                .enumerate()
+                .fold((0, f64::NEG_INFINITY), |best, (ix, variance)| {
+                    match *variance > best.1 {
+                        true => (ix, *variance),
+                        false => best,
+                    }
+                });
+
+        if max_variance <= 0_f64 {
+            break;
+        }
+
+        next = max_ix;
+    }
+
+    let total = match params.weight_mode {
+        WeightMode::Pixels => moments.weight[table_index(SIDE - 1, SIDE - 1, SIDE - 1)],
+        WeightMode::DistinctColors => moments.distinct[table_index(SIDE - 1, SIDE - 1, SIDE - 1)],
+    };
+
+    cubes
+        .iter()
+        .filter_map(|cube| {
+            let box_moments = moments.box_moments(cube);
+
+            if box_moments.weight <= 0_f64 {
+                return None;
+            }
+
+            let [r, g, b] = box_moments
+                .sums
+                .map(|sum| (sum / box_moments.weight).round() as u32);
+            let weight = match params.weight_mode {
+                WeightMode::Pixels => box_moments.weight,
+                WeightMode::DistinctColors => volume(cube, &moments.distinct),
+            };
+
+            Some(ColorPoint::new(
+                RgbColor::new(r, g, b),
+                (weight / total) as f32,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kmeans::cluster_params::InitStrategy;
+
+    fn test_histogram() -> Histogram {
+        let mut histogram = Histogram::new();
+        (0..16_u32).for_each(|i| {
+            (0..3).for_each(|_| histogram.push_color(&RgbColor::new(240 + i, 20, 20)));
+            histogram.push_color(&RgbColor::new(20, 240 + i, 20));
+            histogram.push_color(&RgbColor::new(20, 20, 240 + i));
+        });
+
+        histogram
+    }
+
+    #[test]
+    fn cluster_finds_color_groups() {
+        let mut params = ClusterParams::new(3, 0);
+        params.weight_mode = WeightMode::Pixels;
+
+        let mut centers = cluster(&test_histogram(), &params);
+        centers.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+
+        assert_eq!(centers.len(), 3);
+        assert_eq!(centers[0].color.to_vec(), vec![248, 20, 20]);
+        assert_eq!(centers[0].weight, 0.6);
+        assert_eq!(centers[1].weight, 0.2);
+        assert_eq!(centers[2].weight, 0.2);
+    }
+
+    #[test]
+    fn quantize_with_kmeans_refinement() {
+        let mut params = ClusterParams::new(3, 30);
+        params.init_strategy = InitStrategy::KMeansPlusPlus;
+        params.refine_with_kmeans = true;
+
+        let centers = Wu.quantize(&test_histogram(), &params).centers;
+        assert_eq!(centers.len(), 3);
+        assert!(centers.iter().any(|point| point.color.r > 240));
+        assert!(centers.iter().any(|point| point.color.g > 240));
+        assert!(centers.iter().any(|point| point.color.b > 240));
+    }
+}
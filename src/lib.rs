@@ -0,0 +1,3 @@
+pub mod img_utils;
+pub mod kmeans;
+pub mod web;
@@ -1,13 +1,9 @@
-pub mod img_utils;
-pub mod kmeans;
-pub mod web;
-
-use crate::web::controller;
 use axum::{
     extract::DefaultBodyLimit,
     routing::{get, post},
     Router,
 };
+use image_base_colors::web::controller;
 use std::env;
 use tokio::signal;
 
@@ -16,10 +12,8 @@ async fn main() {
     const DEFAULT_PORT: u16 = 8080;
 
     let app_port = match env::var("APP_PORT") {
-        Ok(val) => val
-            .is_empty()
-            .then(|| DEFAULT_PORT.to_string())
-            .unwrap_or(val),
+        Ok(val) if val.is_empty() => DEFAULT_PORT.to_string(),
+        Ok(val) => val,
         Err(_) => DEFAULT_PORT.to_string(),
     };
 
@@ -1,4 +1,7 @@
-use crate::img_utils::{base_colors, color_point::ColorPoint};
+use crate::img_utils::{
+    base_colors::{self, Palette},
+    color_point::ColorPoint,
+};
 use crate::web::request_parser::Request;
 use axum::{
     body::Bytes,
@@ -7,9 +10,19 @@ use axum::{
     response::IntoResponse,
 };
 use image::{ImageError, ImageOutputFormat};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::io::Cursor;
 
+/// `/info` answers the bare base colors unless the request asks for
+/// the automatic number of clusters, the diagnostics or the background.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Info {
+    BaseColors(Vec<ColorPoint>),
+    Palette(Palette),
+}
+
 pub async fn hello() -> String {
     format!("Image base colors. Version: {}", env!("CARGO_PKG_VERSION"))
 }
@@ -27,11 +40,7 @@ pub async fn info(
         Err(err) => return Err((StatusCode::BAD_REQUEST, err)),
     };
 
-    let base_colors = match get_base_colors_info(
-        &request.file_buffer,
-        request.number_of_clusters,
-        request.max_try_count,
-    ) {
+    let base_colors = match get_base_colors_info(&request) {
         Ok(res) => res,
         Err(err) => {
             return Err((
@@ -57,11 +66,7 @@ pub async fn draw(
         Err(err) => return Err((StatusCode::BAD_REQUEST, err)),
     };
 
-    let base_colors_image = match get_png_image_with_base_colors(
-        &request.file_buffer,
-        request.number_of_clusters,
-        request.max_try_count,
-    ) {
+    let base_colors_image = match get_png_image_with_base_colors(&request) {
         Ok(res) => res,
         Err(err) => {
             return Err((
@@ -86,29 +91,35 @@ pub async fn draw(
     ))
 }
 
-fn get_base_colors_info(
-    buffer: &[u8],
-    number_of_clusters: u32,
-    max_try_count: u32,
-) -> Result<Vec<ColorPoint>, ImageError> {
-    let image = base_colors::open_image_from_bytes(buffer)?;
-
-    Ok(base_colors::kmeans_calculate(
-        &image,
-        number_of_clusters,
-        max_try_count,
-    ))
+fn get_base_colors_info(request: &Request) -> Result<Info, ImageError> {
+    let image = base_colors::open_image_from_bytes(&request.file_buffer)?;
+
+    let mut palette =
+        base_colors::kmeans_calculate(&image, &request.histogram_options, &request.cluster_params);
+
+    if !request.diagnostics {
+        palette.diagnostics = None;
+    }
+
+    let is_detailed = request.diagnostics
+        || request.cluster_params.auto_cluster_count.is_some()
+        || request.histogram_options.background.is_some();
+
+    Ok(match is_detailed {
+        true => Info::Palette(palette),
+        false => Info::BaseColors(palette.base_colors),
+    })
 }
 
-fn get_png_image_with_base_colors(
-    buffer: &[u8],
-    number_of_clusters: u32,
-    max_try_count: u32,
-) -> Result<Vec<u8>, ImageError> {
-    let source_img = base_colors::open_image_from_bytes(buffer)?;
+fn get_png_image_with_base_colors(request: &Request) -> Result<Vec<u8>, ImageError> {
+    let source_img = base_colors::open_image_from_bytes(&request.file_buffer)?;
 
-    let base_colors = base_colors::kmeans_calculate(&source_img, number_of_clusters, max_try_count);
-    let result_img = base_colors::draw(&source_img, &base_colors);
+    let palette = base_colors::kmeans_calculate(
+        &source_img,
+        &request.histogram_options,
+        &request.cluster_params,
+    );
+    let result_img = base_colors::draw(&source_img, &palette.base_colors);
 
     let mut buff = Cursor::new(Vec::new());
     result_img.write_to(&mut buff, ImageOutputFormat::Png)?;
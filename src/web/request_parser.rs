@@ -1,9 +1,29 @@
+use crate::img_utils::{
+    background::BackgroundOptions,
+    color_filter::ColorFilter,
+    color_metric::DistanceMetric,
+    color_space::ColorSpace,
+    histogram::{self, AlphaMode, HistogramOptions, ResizeFilter},
+    region::{Polygon, Rectangle, Region},
+    rgb_color::RgbColor,
+    spatial_weight::SpatialWeighting,
+};
+use crate::kmeans::{
+    cluster_count::{AutoClusterCount, SelectionMethod},
+    cluster_params::{
+        Algorithm, ClusterParams, EmptyClusterStrategy, InitStrategy, MiniBatch, WeightMode,
+        NUMBER_OF_CLUSTERS_LIMIT,
+    },
+    mean_shift,
+};
 use axum::extract::Multipart;
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
 pub struct Request {
-    pub number_of_clusters: u32,
-    pub max_try_count: u32,
+    pub cluster_params: ClusterParams,
+    pub histogram_options: HistogramOptions,
+    /// Whether `/info` reports the k-means diagnostics.
+    pub diagnostics: bool,
     pub file_name: String,
     pub file_buffer: Vec<u8>,
 }
@@ -13,19 +33,103 @@ impl Request {
         params: &HashMap<String, String>,
         multipart: &mut Multipart,
     ) -> Result<Request, String> {
-        let number_of_clusters =
-            get_number_of_clusters(params).unwrap_or(get_number_of_clusters_default());
+        let algorithm = get_algorithm(params).unwrap_or_default();
+
+        let auto_cluster_count = get_auto_cluster_count(params, algorithm)?;
+
+        let number_of_clusters = match &auto_cluster_count {
+            Some(auto) => auto.min_number_of_clusters,
+            None => get_number_of_clusters(params).unwrap_or(get_number_of_clusters_default()),
+        };
 
         let max_try_count = get_max_try_count(params).unwrap_or(get_max_try_count_default());
 
-        let (name, buffer) = match get_image_buffer(multipart).await {
+        let tolerance = get_tolerance(params).unwrap_or_default();
+
+        let n_init = get_n_init(params).unwrap_or(1);
+
+        let mini_batch = get_mini_batch(params);
+
+        let init_strategy = get_init_strategy(params).unwrap_or_default();
+
+        let empty_cluster_strategy = get_empty_cluster_strategy(params).unwrap_or_default();
+
+        let seed = get_seed(params);
+
+        let weight_mode = get_weight_mode(params).unwrap_or_default();
+
+        let color_space = get_color_space(params).unwrap_or_default();
+
+        let metric = get_metric(params).unwrap_or_default();
+
+        let bandwidth = get_bandwidth(params, metric.metric().color_space().unwrap_or(color_space));
+
+        let refine_with_kmeans = get_refine_with_kmeans(params, algorithm, color_space, metric)?;
+
+        let snap_to_existing = get_snap_to_existing(params).unwrap_or_default();
+
+        let diagnostics = get_diagnostics(params).unwrap_or_default();
+
+        let (image_field, mask_field) = get_multipart_fields(multipart).await?;
+
+        let (name, buffer) = match image_field {
             Some(value) => value,
             None => return Err(String::from("Can't read image from request.")),
         };
 
+        let mask = match mask_field {
+            Some(mask_buffer) => match image::load_from_memory(&mask_buffer) {
+                Ok(mask) => Some(mask.to_luma8()),
+                Err(err) => return Err(format!("Can't read mask from request: {err}")),
+            },
+            None => None,
+        };
+
+        let histogram_options = HistogramOptions {
+            bits: get_histogram_bits(params).unwrap_or(histogram::MAX_BITS),
+            max_analysis_dimension: get_max_analysis_dimension(params),
+            resize_filter: get_resize_filter(params).unwrap_or_default(),
+            sample_rate: get_sample_rate(params)?.unwrap_or(1_f32),
+            alpha_threshold: get_alpha_threshold(params).unwrap_or(128),
+            alpha_mode: get_alpha_mode(params).unwrap_or_default(),
+            region: Region {
+                rectangle: get_roi(params)?,
+                polygon: get_polygon(params)?,
+                mask,
+            },
+            spatial_weights: get_spatial_weights(params),
+            background: get_background(params),
+            color_filter: ColorFilter {
+                min_lightness: get_min_lightness(params),
+                max_lightness: get_max_lightness(params),
+                min_chroma: get_min_chroma(params),
+                ignored_colors: get_ignored_colors(params)?,
+                ignore_tolerance: get_ignore_tolerance(params).unwrap_or(16_f32),
+            },
+            existing_colors: algorithm == Algorithm::KMedoids || snap_to_existing,
+        };
+
         Ok(Request {
-            number_of_clusters,
-            max_try_count,
+            cluster_params: ClusterParams {
+                algorithm,
+                number_of_clusters,
+                max_try_count,
+                tolerance,
+                n_init,
+                init_strategy,
+                empty_cluster_strategy,
+                seed,
+                weight_mode,
+                color_space,
+                metric,
+                refine_with_kmeans,
+                auto_cluster_count,
+                mini_batch,
+                bandwidth,
+                snap_to_existing,
+            },
+            histogram_options,
+            diagnostics,
             file_name: name,
             file_buffer: buffer,
         })
@@ -40,46 +144,404 @@ fn get_max_try_count_default() -> u32 {
     30
 }
 
+fn get_min_number_of_clusters_default() -> u32 {
+    2
+}
+
+fn get_max_number_of_clusters_default() -> u32 {
+    10
+}
+
+/// Every number of clusters of the `auto` range runs a full quantization.
+const AUTO_NUMBER_OF_CLUSTERS_LIMIT: u32 = 32;
+
+fn get_algorithm(params: &HashMap<String, String>) -> Option<Algorithm> {
+    const FIELD_NAME: &str = "algorithm";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
 fn get_number_of_clusters(params: &HashMap<String, String>) -> Option<u32> {
     const FIELD_NAME: &str = "number_of_clusters";
 
+    get_filed_value_u32(params, FIELD_NAME).map(|value| value.min(NUMBER_OF_CLUSTERS_LIMIT))
+}
+
+/// `number_of_clusters=auto` selects the number of clusters from a range.
+/// Mean-shift finds the number of colors by itself, so it can't be combined with `auto`.
+fn get_auto_cluster_count(
+    params: &HashMap<String, String>,
+    algorithm: Algorithm,
+) -> Result<Option<AutoClusterCount>, String> {
+    const FIELD_NAME: &str = "number_of_clusters";
+    const AUTO_VALUE: &str = "auto";
+
+    if params.get(FIELD_NAME).map(String::as_str) != Some(AUTO_VALUE) {
+        return Ok(None);
+    }
+
+    if algorithm == Algorithm::MeanShift {
+        return Err(String::from(
+            "'number_of_clusters=auto' can't be used with 'algorithm=mean_shift'.",
+        ));
+    }
+
+    Ok(Some(AutoClusterCount {
+        min_number_of_clusters: get_min_number_of_clusters(params)
+            .unwrap_or(get_min_number_of_clusters_default())
+            .min(AUTO_NUMBER_OF_CLUSTERS_LIMIT),
+        max_number_of_clusters: get_max_number_of_clusters(params)
+            .unwrap_or(get_max_number_of_clusters_default())
+            .min(AUTO_NUMBER_OF_CLUSTERS_LIMIT),
+        method: get_cluster_count_method(params).unwrap_or_default(),
+    }))
+}
+
+fn get_min_number_of_clusters(params: &HashMap<String, String>) -> Option<u32> {
+    const FIELD_NAME: &str = "min_number_of_clusters";
+
+    get_filed_value_u32(params, FIELD_NAME)
+}
+
+fn get_max_number_of_clusters(params: &HashMap<String, String>) -> Option<u32> {
+    const FIELD_NAME: &str = "max_number_of_clusters";
+
     get_filed_value_u32(params, FIELD_NAME)
 }
 
+fn get_cluster_count_method(params: &HashMap<String, String>) -> Option<SelectionMethod> {
+    const FIELD_NAME: &str = "cluster_count_method";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+/// Every iteration is a pass over the histogram, for every restart.
+const MAX_TRY_COUNT_LIMIT: u32 = 300;
+
 fn get_max_try_count(params: &HashMap<String, String>) -> Option<u32> {
     const FIELD_NAME: &str = "max_try_count";
 
+    get_filed_value_u32(params, FIELD_NAME).map(|value| value.min(MAX_TRY_COUNT_LIMIT))
+}
+
+fn get_tolerance(params: &HashMap<String, String>) -> Option<f32> {
+    const FIELD_NAME: &str = "tolerance";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+/// Every restart is a full k-means run.
+const N_INIT_LIMIT: u32 = 16;
+
+fn get_n_init(params: &HashMap<String, String>) -> Option<u32> {
+    const FIELD_NAME: &str = "n_init";
+
+    get_filed_value(params, FIELD_NAME).map(|n_init: u32| n_init.min(N_INIT_LIMIT))
+}
+
+fn get_mini_batch(params: &HashMap<String, String>) -> Option<MiniBatch> {
+    const THRESHOLD_FIELD_NAME: &str = "mini_batch_threshold";
+    const SIZE_FIELD_NAME: &str = "mini_batch_size";
+
+    Some(MiniBatch {
+        min_histogram_size: get_filed_value(params, THRESHOLD_FIELD_NAME)?,
+        batch_size: get_filed_value(params, SIZE_FIELD_NAME)
+            .unwrap_or(get_mini_batch_size_default())
+            .min(MINI_BATCH_SIZE_LIMIT),
+    })
+}
+
+fn get_mini_batch_size_default() -> usize {
+    1024
+}
+
+/// Batches are sampled on every iteration.
+const MINI_BATCH_SIZE_LIMIT: usize = 65536;
+
+/// A narrow kernel makes nearly every distinct color a mode, so the bandwidth
+/// is raised to the minimum of the clustering color space.
+fn get_bandwidth(params: &HashMap<String, String>, color_space: ColorSpace) -> Option<f32> {
+    const FIELD_NAME: &str = "bandwidth";
+
+    get_filed_value(params, FIELD_NAME)
+        .filter(|bandwidth: &f32| *bandwidth > 0_f32)
+        .map(|bandwidth| bandwidth.max(mean_shift::min_bandwidth(color_space)))
+}
+
+fn get_init_strategy(params: &HashMap<String, String>) -> Option<InitStrategy> {
+    const FIELD_NAME: &str = "init_strategy";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_empty_cluster_strategy(params: &HashMap<String, String>) -> Option<EmptyClusterStrategy> {
+    const FIELD_NAME: &str = "empty_cluster_strategy";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_seed(params: &HashMap<String, String>) -> Option<u64> {
+    const FIELD_NAME: &str = "seed";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_weight_mode(params: &HashMap<String, String>) -> Option<WeightMode> {
+    const FIELD_NAME: &str = "weight_mode";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_color_space(params: &HashMap<String, String>) -> Option<ColorSpace> {
+    const FIELD_NAME: &str = "color_space";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_metric(params: &HashMap<String, String>) -> Option<DistanceMetric> {
+    const FIELD_NAME: &str = "metric";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+/// Wu cuts boxes in sRGB, so another color space or metric only applies through the k-means refinement.
+/// The refinement is on by default then, turning it off explicitly is an error.
+fn get_refine_with_kmeans(
+    params: &HashMap<String, String>,
+    algorithm: Algorithm,
+    color_space: ColorSpace,
+    metric: DistanceMetric,
+) -> Result<bool, String> {
+    const FIELD_NAME: &str = "refine_with_kmeans";
+
+    let needs_refinement = algorithm == Algorithm::Wu
+        && (color_space != ColorSpace::default() || metric != DistanceMetric::default());
+
+    match get_filed_value(params, FIELD_NAME) {
+        Some(false) if needs_refinement => Err(String::from(
+            "'color_space' and 'metric' need 'refine_with_kmeans=true' with 'algorithm=wu'.",
+        )),
+        Some(refine_with_kmeans) => Ok(refine_with_kmeans),
+        None => Ok(needs_refinement),
+    }
+}
+
+fn get_snap_to_existing(params: &HashMap<String, String>) -> Option<bool> {
+    const FIELD_NAME: &str = "snap_to_existing";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_diagnostics(params: &HashMap<String, String>) -> Option<bool> {
+    const FIELD_NAME: &str = "diagnostics";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_histogram_bits(params: &HashMap<String, String>) -> Option<u32> {
+    const FIELD_NAME: &str = "histogram_bits";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_max_analysis_dimension(params: &HashMap<String, String>) -> Option<u32> {
+    const FIELD_NAME: &str = "max_analysis_dimension";
+
     get_filed_value_u32(params, FIELD_NAME)
 }
 
-async fn get_image_buffer(multipart: &mut Multipart) -> Option<(String, Vec<u8>)> {
-    let field_opt = match multipart.next_field().await {
-        Ok(value) => value,
-        Err(err) => {
-            println!("Error: {err}");
-            return None;
-        }
-    };
+fn get_resize_filter(params: &HashMap<String, String>) -> Option<ResizeFilter> {
+    const FIELD_NAME: &str = "resize_filter";
 
-    let field = field_opt?;
+    get_filed_value(params, FIELD_NAME)
+}
 
-    let name = match field.name() {
-        Some(value) => String::from(value),
-        None => String::new(),
-    };
+/// A share outside `(0, 1]` would count no pixel at all, or not be a share.
+fn get_sample_rate(params: &HashMap<String, String>) -> Result<Option<f32>, String> {
+    const FIELD_NAME: &str = "sample_rate";
 
-    let buffer = match field.bytes().await {
-        Ok(value) => value.to_vec(),
-        Err(err) => {
-            println!("Error: {err}");
-            return None;
+    match get_checked_filed_value(params, FIELD_NAME)? {
+        Some(sample_rate) if !(sample_rate > 0_f32 && sample_rate <= 1_f32) => Err(format!(
+            "Invalid '{FIELD_NAME}': expected a value above 0 and at most 1, got {sample_rate}."
+        )),
+        sample_rate => Ok(sample_rate),
+    }
+}
+
+fn get_alpha_threshold(params: &HashMap<String, String>) -> Option<u8> {
+    const FIELD_NAME: &str = "alpha_threshold";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_alpha_mode(params: &HashMap<String, String>) -> Option<AlphaMode> {
+    const FIELD_NAME: &str = "alpha_mode";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_roi(params: &HashMap<String, String>) -> Result<Option<Rectangle>, String> {
+    const FIELD_NAME: &str = "roi";
+
+    get_checked_filed_value(params, FIELD_NAME)
+}
+
+fn get_polygon(params: &HashMap<String, String>) -> Result<Option<Polygon>, String> {
+    const FIELD_NAME: &str = "polygon";
+
+    get_checked_filed_value(params, FIELD_NAME)
+}
+
+fn get_spatial_weights(params: &HashMap<String, String>) -> Vec<SpatialWeighting> {
+    const CENTER_BIAS_FIELD_NAME: &str = "center_bias";
+    const SALIENCY_FIELD_NAME: &str = "saliency";
+    const BORDER_MARGIN_FIELD_NAME: &str = "border_margin";
+
+    let center_bias = get_filed_value(params, CENTER_BIAS_FIELD_NAME)
+        .map(|sigma| SpatialWeighting::CenterBias { sigma });
+    let saliency = get_filed_value(params, SALIENCY_FIELD_NAME)
+        .filter(|saliency: &bool| *saliency)
+        .map(|_| SpatialWeighting::Saliency);
+    let border_margin = get_filed_value(params, BORDER_MARGIN_FIELD_NAME)
+        .map(|margin| SpatialWeighting::BorderMargin { margin });
+
+    [center_bias, saliency, border_margin]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+fn get_background(params: &HashMap<String, String>) -> Option<BackgroundOptions> {
+    const MODE_FIELD_NAME: &str = "background";
+    const TOLERANCE_FIELD_NAME: &str = "background_tolerance";
+
+    Some(BackgroundOptions {
+        mode: get_filed_value(params, MODE_FIELD_NAME)?,
+        tolerance: get_filed_value(params, TOLERANCE_FIELD_NAME)
+            .unwrap_or(get_background_tolerance_default()),
+    })
+}
+
+fn get_background_tolerance_default() -> f32 {
+    32_f32
+}
+
+fn get_min_lightness(params: &HashMap<String, String>) -> Option<f32> {
+    const FIELD_NAME: &str = "min_lightness";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_max_lightness(params: &HashMap<String, String>) -> Option<f32> {
+    const FIELD_NAME: &str = "max_lightness";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+fn get_min_chroma(params: &HashMap<String, String>) -> Option<f32> {
+    const FIELD_NAME: &str = "min_chroma";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+/// Comma separated hex colors. An invalid color fails the request,
+/// the colors asked to be left out would end up in the palette otherwise.
+fn get_ignored_colors(params: &HashMap<String, String>) -> Result<Vec<RgbColor>, String> {
+    const FIELD_NAME: &str = "ignore_colors";
+
+    match params.get(FIELD_NAME) {
+        Some(value) => value
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("Invalid '{FIELD_NAME}': {err}")),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn get_ignore_tolerance(params: &HashMap<String, String>) -> Option<f32> {
+    const FIELD_NAME: &str = "ignore_tolerance";
+
+    get_filed_value(params, FIELD_NAME)
+}
+
+/// Reads the image (first field) and the optional `mask` field.
+/// Once the image is read, a field that can't be read is an error: it may be the mask.
+async fn get_multipart_fields(
+    multipart: &mut Multipart,
+) -> Result<(Option<(String, Vec<u8>)>, Option<Vec<u8>>), String> {
+    const MASK_FIELD_NAME: &str = "mask";
+
+    let mut image = None;
+    let mut mask = None;
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(value)) => value,
+            Ok(None) => break,
+            Err(err) if image.is_some() => {
+                return Err(format!("Can't read multipart field from request: {err}"))
+            }
+            Err(err) => {
+                println!("Error: {err}");
+                break;
+            }
+        };
+
+        let name = match field.name() {
+            Some(value) => String::from(value),
+            None => String::new(),
+        };
+
+        let buffer = match field.bytes().await {
+            Ok(value) => value.to_vec(),
+            Err(err) if image.is_some() || name == MASK_FIELD_NAME => {
+                return Err(format!("Can't read field '{name}' from request: {err}"))
+            }
+            Err(err) => {
+                println!("Error: {err}");
+                break;
+            }
+        };
+
+        match name == MASK_FIELD_NAME {
+            true => mask = Some(buffer),
+            false if image.is_none() => image = Some((name, buffer)),
+            false => println!("Extra multipart field '{name}' is ignored."),
         }
-    };
+    }
 
-    Some((name, buffer))
+    Ok((image, mask))
 }
 
 fn get_filed_value_u32(params: &HashMap<String, String>, field_name: &str) -> Option<u32> {
+    get_filed_value(params, field_name)
+}
+
+/// Missing values are `None`, values that don't parse are an error.
+fn get_checked_filed_value<T>(
+    params: &HashMap<String, String>,
+    field_name: &str,
+) -> Result<Option<T>, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    params
+        .get(field_name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|err| format!("Invalid '{field_name}': {err}"))
+        })
+        .transpose()
+}
+
+fn get_filed_value<T>(params: &HashMap<String, String>, field_name: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
     let field_value_str = match params.get(field_name) {
         Some(value) => value,
         None => {
@@ -88,7 +550,7 @@ fn get_filed_value_u32(params: &HashMap<String, String>, field_name: &str) -> Op
         }
     };
 
-    match field_value_str.parse::<u32>() {
+    match field_value_str.parse::<T>() {
         Ok(number) => Some(number),
         Err(err) => {
             println!("Parse error: {err}");
@@ -96,3 +558,236 @@ fn get_filed_value_u32(params: &HashMap<String, String>, field_name: &str) -> Op
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::FromRequest, http::header::CONTENT_TYPE};
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn auto_cluster_count_is_capped() {
+        let auto = get_auto_cluster_count(
+            &params(&[
+                ("number_of_clusters", "auto"),
+                ("min_number_of_clusters", "50"),
+                ("max_number_of_clusters", "100000"),
+            ]),
+            Algorithm::KMeans,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(auto.min_number_of_clusters, AUTO_NUMBER_OF_CLUSTERS_LIMIT);
+        assert_eq!(auto.max_number_of_clusters, AUTO_NUMBER_OF_CLUSTERS_LIMIT);
+    }
+
+    #[test]
+    fn auto_cluster_count_rejects_mean_shift() {
+        let auto = params(&[("number_of_clusters", "auto")]);
+
+        assert!(get_auto_cluster_count(&auto, Algorithm::MeanShift).is_err());
+        assert!(get_auto_cluster_count(&auto, Algorithm::Wu)
+            .unwrap()
+            .is_some());
+        assert!(get_auto_cluster_count(
+            &params(&[("number_of_clusters", "4")]),
+            Algorithm::MeanShift
+        )
+        .unwrap()
+        .is_none());
+    }
+
+    #[test]
+    fn wu_refines_in_requested_color_space_and_metric() {
+        let refine = |pairs: &[(&str, &str)], algorithm, color_space, metric| {
+            get_refine_with_kmeans(&params(pairs), algorithm, color_space, metric)
+        };
+
+        assert_eq!(
+            refine(
+                &[],
+                Algorithm::Wu,
+                ColorSpace::default(),
+                DistanceMetric::default()
+            ),
+            Ok(false)
+        );
+        assert_eq!(
+            refine(
+                &[],
+                Algorithm::Wu,
+                ColorSpace::Lab,
+                DistanceMetric::default()
+            ),
+            Ok(true)
+        );
+        assert_eq!(
+            refine(
+                &[],
+                Algorithm::Wu,
+                ColorSpace::default(),
+                DistanceMetric::Ciede2000
+            ),
+            Ok(true)
+        );
+        assert!(refine(
+            &[("refine_with_kmeans", "false")],
+            Algorithm::Wu,
+            ColorSpace::Oklab,
+            DistanceMetric::default()
+        )
+        .is_err());
+        // other algorithms cluster in the requested color space themselves.
+        assert_eq!(
+            refine(
+                &[],
+                Algorithm::KMeans,
+                ColorSpace::Lab,
+                DistanceMetric::Ciede2000
+            ),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn mini_batch_size_is_capped() {
+        let mini_batch = get_mini_batch(&params(&[
+            ("mini_batch_threshold", "0"),
+            ("mini_batch_size", "18446744073709551615"),
+        ]))
+        .unwrap();
+
+        assert_eq!(mini_batch.batch_size, MINI_BATCH_SIZE_LIMIT);
+    }
+
+    #[test]
+    fn bandwidth_is_raised_to_the_minimum() {
+        assert_eq!(
+            get_bandwidth(&params(&[("bandwidth", "1")]), ColorSpace::Srgb),
+            Some(mean_shift::min_bandwidth(ColorSpace::Srgb))
+        );
+        assert_eq!(
+            get_bandwidth(&params(&[("bandwidth", "20")]), ColorSpace::Lab),
+            Some(20_f32)
+        );
+        assert_eq!(
+            get_bandwidth(&params(&[("bandwidth", "0")]), ColorSpace::Srgb),
+            None
+        );
+    }
+
+    #[test]
+    fn sample_rate_out_of_range_is_an_error() {
+        assert_eq!(
+            get_sample_rate(&params(&[("sample_rate", "0.25")])),
+            Ok(Some(0.25))
+        );
+        assert_eq!(
+            get_sample_rate(&params(&[("sample_rate", "1")])),
+            Ok(Some(1.0))
+        );
+        ["0", "-0.5", "1.5", "NaN", "x"].iter().for_each(|value| {
+            assert!(get_sample_rate(&params(&[("sample_rate", value)])).is_err());
+        });
+        assert_eq!(get_sample_rate(&params(&[])), Ok(None));
+    }
+
+    #[test]
+    fn invalid_ignored_color_is_an_error() {
+        assert_eq!(
+            get_ignored_colors(&params(&[("ignore_colors", "ffffff,#000000")])),
+            Ok(vec![RgbColor::new(255, 255, 255), RgbColor::new(0, 0, 0)])
+        );
+        assert!(get_ignored_colors(&params(&[("ignore_colors", "ffffff,black")])).is_err());
+        assert_eq!(get_ignored_colors(&params(&[])), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn number_of_clusters_is_capped() {
+        assert_eq!(
+            get_number_of_clusters(&params(&[("number_of_clusters", "50000")])),
+            Some(NUMBER_OF_CLUSTERS_LIMIT)
+        );
+        assert_eq!(
+            get_number_of_clusters(&params(&[("number_of_clusters", "6")])),
+            Some(6)
+        );
+    }
+
+    #[test]
+    fn invalid_region_is_an_error() {
+        assert_eq!(
+            get_roi(&params(&[("roi", "0,0,400,300")])),
+            Ok(Some(Rectangle {
+                x: 0,
+                y: 0,
+                width: 400,
+                height: 300,
+            }))
+        );
+        assert!(get_roi(&params(&[("roi", "0,0,400")])).is_err());
+        assert!(get_polygon(&params(&[("polygon", "0,0,10,0,10,10")]))
+            .unwrap()
+            .is_some());
+        assert!(get_polygon(&params(&[("polygon", "0,0,10,x,10,10")])).is_err());
+        assert_eq!(get_roi(&params(&[])), Ok(None));
+        assert_eq!(get_polygon(&params(&[])), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn unreadable_mask_is_an_error() {
+        let multipart = |body: &'static str| async move {
+            let request = axum::http::Request::builder()
+                .header(CONTENT_TYPE, "multipart/form-data; boundary=X")
+                .body(Body::from(body.replace('\n', "\r\n")))
+                .unwrap();
+
+            Multipart::from_request(request, &()).await.unwrap()
+        };
+
+        let mut complete = multipart(concat!(
+            "--X\nContent-Disposition: form-data; name=\"file_name\"; filename=\"a.png\"\n\nimage\n",
+            "--X\nContent-Disposition: form-data; name=\"mask\"\n\nmask\n--X--\n"
+        ))
+        .await;
+        let (image, mask) = get_multipart_fields(&mut complete).await.unwrap();
+        assert_eq!(image.unwrap().1, b"image");
+        assert_eq!(mask.unwrap(), b"mask");
+
+        // the body ends inside the mask field.
+        let mut truncated = multipart(concat!(
+            "--X\nContent-Disposition: form-data; name=\"file_name\"; filename=\"a.png\"\n\nimage\n",
+            "--X\nContent-Disposition: form-data; name=\"mask\"\n\nmas"
+        ))
+        .await;
+        assert!(get_multipart_fields(&mut truncated).await.is_err());
+    }
+
+    #[test]
+    fn max_try_count_is_capped() {
+        assert_eq!(
+            get_max_try_count(&params(&[("max_try_count", "4000000000")])),
+            Some(MAX_TRY_COUNT_LIMIT)
+        );
+        assert_eq!(
+            get_max_try_count(&params(&[("max_try_count", "50")])),
+            Some(50)
+        );
+    }
+
+    #[test]
+    fn n_init_is_capped() {
+        assert_eq!(
+            get_n_init(&params(&[("n_init", "4000000000")])),
+            Some(N_INIT_LIMIT)
+        );
+        assert_eq!(get_n_init(&params(&[("n_init", "3")])), Some(3));
+    }
+}